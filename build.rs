@@ -0,0 +1,50 @@
+// Copyright 2021 Tom A. Wagner <tom.a.wagner@protonmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License version 3 as published by
+// the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Compile the settings schema next to the build artifacts,
+//! so that Helvum can be run from a checkout without installing the schema first.
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+const SCHEMA: &str = "data/org.pipewire.Helvum.gschema.xml";
+
+fn main() {
+    println!("cargo:rerun-if-changed={SCHEMA}");
+
+    let schema_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR should be set by cargo"))
+        .join("schemas");
+    let copied = fs::create_dir_all(&schema_dir)
+        .and_then(|_| fs::copy(SCHEMA, schema_dir.join("org.pipewire.Helvum.gschema.xml")));
+    if let Err(e) = copied {
+        println!("cargo:warning=Failed to copy settings schema: {e}");
+        return;
+    }
+
+    match Command::new("glib-compile-schemas")
+        .arg(&schema_dir)
+        .status()
+    {
+        Ok(status) if status.success() => {
+            println!("cargo:rustc-env=HELVUM_SCHEMA_DIR={}", schema_dir.display());
+        }
+        Ok(status) => {
+            println!("cargo:warning=glib-compile-schemas failed with {status}");
+        }
+        Err(e) => {
+            println!("cargo:warning=Failed to run glib-compile-schemas: {e}");
+        }
+    }
+}
@@ -21,10 +21,16 @@ use adw::{
     prelude::*,
     subclass::prelude::*,
 };
-use log::error;
-use pipewire::channel::Sender;
+use std::{rc::Rc, time::Duration};
 
-use crate::{graph_manager::GraphManager, ui, GtkMessage, PipewireMessage};
+use log::{error, info, warn};
+
+use crate::{
+    backend::{self, Backend, ScriptedBackend, TraceWriter},
+    graph_manager::{media_class_key, GraphManager, MEDIA_CLASSES},
+    pipewire_connection::{self, PipewireBackend},
+    ui, GtkMessage, PipewireMessage,
+};
 
 static STYLE: &str = include_str!("style.css");
 static APP_ID: &str = "org.pipewire.Helvum";
@@ -33,17 +39,45 @@ static AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 
 const DEFAULT_REMOTE_NAME: &str = "Default Remote";
 
+/// How many recently used remotes are remembered.
+const MAX_RECENT_REMOTES: usize = 8;
+
+/// A connection to a pipewire remote, whose graph is shown in its own tab of the window.
+struct Connection {
+    page: adw::TabPage,
+    backend: Rc<dyn Backend>,
+    /// Whether the graph is replayed from a trace instead of being received from a remote.
+    is_replay: bool,
+    _graph_manager: GraphManager,
+}
+
+impl Connection {
+    /// Ask the backend of this connection to stop, returning it so that it can be waited for later.
+    fn terminate(self) -> Rc<dyn Backend> {
+        self.backend.terminate();
+        self.backend
+    }
+}
+
 mod imp {
     use super::*;
 
-    use std::cell::OnceCell;
+    use std::{
+        cell::{OnceCell, RefCell},
+        path::{Path, PathBuf},
+    };
 
     use adw::subclass::prelude::AdwApplicationImpl;
 
     #[derive(Default)]
     pub struct Application {
         pub(super) window: ui::Window,
-        pub(super) graph_manager: OnceCell<GraphManager>,
+        pub(super) settings: OnceCell<gio::Settings>,
+        pub(super) connections: RefCell<Vec<Connection>>,
+        /// Backends of closed connections that may still be shutting down, which are waited for on exit.
+        pub(super) closed_backends: RefCell<Vec<Rc<dyn Backend>>>,
+        /// File that the messages of the next connection should be recorded to.
+        pub(super) record_path: RefCell<Option<PathBuf>>,
     }
 
     #[glib::object_subclass]
@@ -53,24 +87,84 @@ mod imp {
         type ParentType = adw::Application;
     }
 
-    impl ObjectImpl for Application {}
+    impl ObjectImpl for Application {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            // Disconnect from the remote of a tab once it is closed.
+            self.window.tab_view().connect_close_page(
+                clone!(@weak self as imp => @default-return false, move |_, page| {
+                    let connection = {
+                        let mut connections = imp.connections.borrow_mut();
+                        connections
+                            .iter()
+                            .position(|connection| &connection.page == page)
+                            .map(|index| connections.remove(index))
+                    };
+
+                    if let Some(connection) = connection {
+                        info!("Disconnecting from remote \"{}\"", page.title());
+                        // Waiting for the backend here would freeze the UI if the remote stalls.
+                        let backend = connection.terminate();
+                        imp.closed_backends.borrow_mut().push(backend);
+                    }
+
+                    false
+                }),
+            );
+        }
+    }
+
     impl ApplicationImpl for Application {
         fn activate(&self) {
             let app = &*self.obj();
+            let window = &self.window;
 
-            let graphview = self.window.graph();
-
-            self.window.set_application(Some(app));
+            window.set_application(Some(app));
 
+            // The zoom actions always apply to the graph of the currently selected tab.
             let zoom_set_action =
                 gio::SimpleAction::new("set-zoom", Some(&f64::static_variant_type()));
-            zoom_set_action.connect_activate(clone!(@weak graphview => move|_, param| {
+            zoom_set_action.connect_activate(clone!(@weak window => move|_, param| {
                 let zoom_factor = param.unwrap().get::<f64>().unwrap();
-                graphview.set_zoom_factor(zoom_factor, None)
+                if let Some(graphview) = window.current_graph() {
+                    graphview.set_zoom_factor(zoom_factor, None)
+                }
+            }));
+            window.add_action(&zoom_set_action);
+
+            let zoom_to_fit_action = gio::SimpleAction::new("zoom-to-fit", None);
+            zoom_to_fit_action.connect_activate(clone!(@weak window => move |_, _| {
+                if let Some(graphview) = window.current_graph() {
+                    graphview.zoom_to_fit();
+                }
+            }));
+            window.add_action(&zoom_to_fit_action);
+
+            let zoom_to_selection_action = gio::SimpleAction::new("zoom-to-selection", None);
+            zoom_to_selection_action.connect_activate(clone!(@weak window => move |_, _| {
+                if let Some(graphview) = window.current_graph() {
+                    graphview.zoom_to_selection();
+                }
             }));
-            self.window.add_action(&zoom_set_action);
+            window.add_action(&zoom_to_selection_action);
 
-            self.window.show();
+            window.show();
+        }
+
+        fn shutdown(&self) {
+            let mut backends = self.closed_backends.take();
+            backends.extend(
+                self.connections
+                    .take()
+                    .into_iter()
+                    .map(Connection::terminate),
+            );
+            for backend in backends {
+                backend.join();
+            }
+
+            self.parent_shutdown();
         }
 
         fn startup(&self) {
@@ -112,7 +206,75 @@ mod imp {
                     obj.imp().show_about_dialog();
                 })
                 .build();
-            obj.add_action_entries([action_about]);
+            let action_connect_remote = gio::ActionEntry::builder("connect-remote")
+                .activate(|obj: &super::Application, _, _| {
+                    obj.imp().show_connect_dialog();
+                })
+                .build();
+            let action_switch_remote = gio::ActionEntry::builder("switch-remote")
+                .parameter_type(Some(&String::static_variant_type()))
+                .activate(|obj: &super::Application, _, param| {
+                    let Some(remote) = param.and_then(|param| param.get::<String>()) else {
+                        return;
+                    };
+                    // The default remote is represented by an empty string.
+                    let remote = (!remote.is_empty()).then_some(remote);
+                    obj.imp().switch_remote(remote);
+                })
+                .build();
+            let action_switch_remote_other = gio::ActionEntry::builder("switch-remote-other")
+                .activate(|obj: &super::Application, _, _| {
+                    obj.imp().show_switch_dialog();
+                })
+                .build();
+            obj.add_action_entries([
+                action_about,
+                action_connect_remote,
+                action_switch_remote,
+                action_switch_remote_other,
+            ]);
+            obj.set_accels_for_action("app.connect-remote", &["<Control>T"]);
+
+            // Refresh the list of remotes each time the remote picker is opened,
+            // as remotes may have appeared or vanished in the meantime.
+            self.window
+                .remote_button()
+                .set_create_popup_func(clone!(@weak obj => move |_| {
+                    obj.imp().update_remote_menu();
+                }));
+
+            self.setup_filter_actions();
+        }
+
+        /// Add actions for toggling which objects are shown in the graph, and how they are shown.
+        ///
+        /// The state of these actions is stored in the settings, so that it persists between runs.
+        fn setup_filter_actions(&self) {
+            let obj = &*self.obj();
+            let settings = self.settings.get().expect("settings should be set");
+
+            for key in [
+                "show-audio",
+                "show-video",
+                "show-midi",
+                "show-virtual-nodes",
+                "show-monitor-ports",
+                "port-sort-mode",
+                "port-label-mode",
+                "link-style",
+                "animate-links",
+                "write-node-labels",
+                "pin-stream-targets",
+            ] {
+                obj.add_action(&settings.create_action(key));
+            }
+
+            let media_class_menu = self.window.media_class_menu();
+            for media_class in MEDIA_CLASSES.iter().copied() {
+                let key = media_class_key(media_class);
+                obj.add_action(&settings.create_action(&key));
+                media_class_menu.append(Some(media_class), Some(&format!("app.{key}")));
+            }
         }
 
         fn show_about_dialog(&self) {
@@ -135,7 +297,270 @@ mod imp {
             about_window.present();
         }
 
-        pub(super) fn setup_options(&self, pw_sender: Sender<GtkMessage>) {
+        /// Show a dialog that lets the user pick a remote to connect to in a new tab.
+        fn show_connect_dialog(&self) {
+            self.show_remote_dialog("Connect to Remote", Self::connect_to_remote);
+        }
+
+        /// Show a dialog that lets the user pick a remote that the selected tab should switch to.
+        fn show_switch_dialog(&self) {
+            self.show_remote_dialog("Switch Remote", Self::switch_remote);
+        }
+
+        /// Show a dialog that lets the user pick a remote, calling `on_picked` with the picked remote.
+        ///
+        /// Remotes found in the runtime directory are offered in a list,
+        /// but any socket name or path can be entered.
+        fn show_remote_dialog(&self, heading: &str, on_picked: fn(&Self, Option<String>)) {
+            let dialog = adw::MessageDialog::new(Some(&self.window), Some(heading), None);
+            dialog.add_responses(&[("cancel", "_Cancel"), ("connect", "_Connect")]);
+            dialog.set_response_appearance("connect", adw::ResponseAppearance::Suggested);
+            dialog.set_default_response(Some("connect"));
+            dialog.set_close_response("cancel");
+
+            let list = gtk::ListBox::new();
+            list.set_selection_mode(gtk::SelectionMode::None);
+            list.add_css_class("boxed-list");
+
+            let entry = adw::EntryRow::new();
+            entry.set_title("Socket Name or Path");
+            entry.set_activates_default(true);
+            list.append(&entry);
+
+            for remote in pipewire_connection::available_remotes() {
+                let row = adw::ActionRow::builder()
+                    .title(remote)
+                    .activatable(true)
+                    .build();
+                list.append(&row);
+            }
+
+            list.connect_row_activated(clone!(@weak entry => move |_, row| {
+                if let Some(row) = row.downcast_ref::<adw::ActionRow>() {
+                    entry.set_text(&row.title());
+                }
+            }));
+
+            dialog.set_extra_child(Some(&list));
+
+            dialog.connect_response(
+                Some("connect"),
+                clone!(@weak self as imp, @weak entry => move |_, _| {
+                    let remote = entry.text();
+                    let remote = (!remote.is_empty()).then(|| remote.to_string());
+                    on_picked(&imp, remote);
+                }),
+            );
+
+            dialog.present();
+        }
+
+        /// Connect to the specified remote, or the default one if `None`, and show its graph in a new tab.
+        pub(super) fn connect_to_remote(&self, remote: Option<String>) {
+            let settings = self.settings.get().expect("settings should be set");
+
+            let backoff = pipewire_connection::Backoff {
+                initial: Duration::from_millis(settings.uint("reconnect-initial-delay").into()),
+                max: Duration::from_millis(settings.uint("reconnect-max-delay").into()),
+            };
+            let (backend, gtk_receiver) = PipewireBackend::spawn(backoff);
+            let backend = Rc::new(backend);
+
+            let title = remote.as_deref().unwrap_or(DEFAULT_REMOTE_NAME);
+            let graph_manager = self.add_tab(title, backend.clone(), gtk_receiver, false);
+
+            if let Some(path) = self.record_path.take() {
+                match TraceWriter::create(&path) {
+                    Ok(writer) => {
+                        info!("Recording messages to {}", path.display());
+                        graph_manager.record_to(writer);
+                    }
+                    Err(e) => error!("Failed to create trace file {}: {e}", path.display()),
+                }
+            }
+
+            info!("Connecting to remote \"{title}\"");
+            self.remember_remote(remote.as_deref());
+            backend.send(GtkMessage::Connect(remote));
+        }
+
+        /// Replay the messages recorded in the trace file in a new tab, without connecting to a remote.
+        ///
+        /// The recorded timing is sped up by the factor `speed`, or ignored if it is zero.
+        fn replay(&self, path: &Path, speed: f64) {
+            let trace = match backend::read_trace(path) {
+                Ok(trace) => trace,
+                Err(e) => {
+                    error!("Failed to read trace file {}: {e}", path.display());
+                    return;
+                }
+            };
+
+            info!(
+                "Replaying {} messages from {} at speed {speed}",
+                trace.len(),
+                path.display()
+            );
+            let (backend, gtk_receiver) = ScriptedBackend::timed(trace, speed);
+
+            let title = format!(
+                "Replay of {}",
+                path.file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy()
+            );
+            self.add_tab(&title, Rc::new(backend), gtk_receiver, true);
+        }
+
+        /// Show a generated graph with the specified number of links in a new tab,
+        /// and log how long frames take while a node is moved around on it.
+        #[cfg(feature = "benchmark")]
+        fn benchmark(&self, link_count: u32) {
+            info!("Benchmarking a synthetic graph with {link_count} links");
+            let (backend, gtk_receiver) =
+                ScriptedBackend::new(backend::synthetic_graph(link_count));
+
+            let title = format!("Benchmark with {link_count} Links");
+            let graph_manager = self.add_tab(&title, Rc::new(backend), gtk_receiver, true);
+            graph_manager.graph().start_benchmark();
+        }
+
+        /// Show the graph received from the backend in a new tab.
+        fn add_tab(
+            &self,
+            title: &str,
+            backend: Rc<dyn Backend>,
+            gtk_receiver: async_channel::Receiver<PipewireMessage>,
+            is_replay: bool,
+        ) -> GraphManager {
+            let settings = self.settings.get().expect("settings should be set");
+
+            let view = ui::RemoteView::new();
+            let graph_manager = GraphManager::new(
+                &view.graph(),
+                &view.connection_banner(),
+                &view.toast_overlay(),
+                settings,
+                backend.clone(),
+                gtk_receiver,
+            );
+
+            let tab_view = self.window.tab_view();
+            let page = tab_view.append(&view);
+            page.set_title(title);
+            page.set_tooltip(title);
+            tab_view.set_selected_page(&page);
+
+            self.connections.borrow_mut().push(Connection {
+                page,
+                backend,
+                is_replay,
+                _graph_manager: graph_manager.clone(),
+            });
+
+            graph_manager
+        }
+
+        /// Switch the connection of the selected tab to the specified remote, or the default one if `None`.
+        ///
+        /// The graph of the tab is cleared once the pipewire thread disconnected from the previous remote.
+        fn switch_remote(&self, remote: Option<String>) {
+            let Some(page) = self.window.tab_view().selected_page() else {
+                return;
+            };
+
+            let connections = self.connections.borrow();
+            let Some(connection) = connections
+                .iter()
+                .find(|connection| connection.page == page)
+            else {
+                log::warn!("Selected tab has no connection");
+                return;
+            };
+            if connection.is_replay {
+                log::warn!("Cannot switch the remote of a replayed trace");
+                return;
+            }
+
+            let title = remote.as_deref().unwrap_or(DEFAULT_REMOTE_NAME);
+            page.set_title(title);
+            page.set_tooltip(title);
+
+            info!("Switching to remote \"{title}\"");
+            self.remember_remote(remote.as_deref());
+            connection.backend.send(GtkMessage::Connect(remote));
+        }
+
+        /// Add the remote to the front of the list of recently used remotes.
+        fn remember_remote(&self, remote: Option<&str>) {
+            let settings = self.settings.get().expect("settings should be set");
+
+            // The default remote is stored as an empty string.
+            let remote = remote.unwrap_or_default();
+            let mut recent_remotes: Vec<String> = settings
+                .strv("recent-remotes")
+                .iter()
+                .map(ToString::to_string)
+                .filter(|recent| recent != remote)
+                .collect();
+            recent_remotes.insert(0, remote.to_string());
+            recent_remotes.truncate(MAX_RECENT_REMOTES);
+
+            if let Err(e) = settings.set_strv("recent-remotes", recent_remotes) {
+                error!("Failed to save recent remotes: {e}");
+            }
+        }
+
+        /// Fill the menu of the remote picker in the header bar with recently used and available remotes.
+        fn update_remote_menu(&self) {
+            let settings = self.settings.get().expect("settings should be set");
+            let menu = self.window.remote_menu();
+            menu.remove_all();
+
+            let recent_remotes: Vec<String> = settings
+                .strv("recent-remotes")
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+
+            let remote_item = |remote: &str| {
+                let label = if remote.is_empty() {
+                    DEFAULT_REMOTE_NAME
+                } else {
+                    remote
+                };
+                let item = gio::MenuItem::new(Some(label), None);
+                item.set_action_and_target_value(
+                    Some("app.switch-remote"),
+                    Some(&remote.to_variant()),
+                );
+                item
+            };
+
+            let recent_section = gio::Menu::new();
+            for remote in &recent_remotes {
+                recent_section.append_item(&remote_item(remote));
+            }
+            if recent_section.n_items() > 0 {
+                menu.append_section(Some("Recent Remotes"), &recent_section);
+            }
+
+            let available_section = gio::Menu::new();
+            for remote in pipewire_connection::available_remotes() {
+                if !recent_remotes.contains(&remote) {
+                    available_section.append_item(&remote_item(&remote));
+                }
+            }
+            if available_section.n_items() > 0 {
+                menu.append_section(Some("Available Remotes"), &available_section);
+            }
+
+            let other_section = gio::Menu::new();
+            other_section.append(Some("_Other Remote…"), Some("app.switch-remote-other"));
+            menu.append_section(None, &other_section);
+        }
+
+        pub(super) fn setup_options(&self) {
             let obj = &*self.obj();
 
             obj.add_main_option(
@@ -147,17 +572,82 @@ mod imp {
                 Some("PATH"),
             );
 
-            let current_remote_label = obj.imp().window.current_remote_label();
-            obj.connect_handle_local_options(clone!(@strong pw_sender => move |_, opts| {
+            obj.add_main_option(
+                "record",
+                glib::char::Char::from(b'r'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::Filename,
+                "Record all messages received from the remote to FILE",
+                Some("FILE"),
+            );
+
+            obj.add_main_option(
+                "replay",
+                glib::char::Char::from(b'R'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::Filename,
+                "Replay the graph recorded in FILE with its original timing instead of connecting to a remote",
+                Some("FILE"),
+            );
+
+            obj.add_main_option(
+                "replay-speed",
+                glib::char::Char::from(b'S'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::Double,
+                "Replay FACTOR times faster than recorded, or as fast as possible if FACTOR is 0",
+                Some("FACTOR"),
+            );
+
+            #[cfg(feature = "benchmark")]
+            obj.add_main_option(
+                "benchmark",
+                glib::char::Char::from(b'b'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::Int,
+                "Measure drawing performance on a generated graph with LINKS links",
+                Some("LINKS"),
+            );
+
+            obj.connect_handle_local_options(|obj, opts| {
+                let imp = obj.imp();
+
+                #[cfg(feature = "benchmark")]
+                if let Ok(Some(link_count)) = opts.lookup::<i32>("benchmark") {
+                    match u32::try_from(link_count) {
+                        Ok(link_count) => imp.benchmark(link_count),
+                        Err(_) => error!("Invalid number of links for benchmark: {link_count}"),
+                    }
+                    return -1;
+                }
+
+                if let Ok(Some(path)) = opts.lookup::<PathBuf>("replay") {
+                    let speed = match opts.lookup::<f64>("replay-speed") {
+                        Ok(Some(speed)) if speed >= 0.0 => speed,
+                        Ok(None) => 1.0,
+                        Ok(Some(speed)) => {
+                            error!("Invalid replay speed {speed}, replaying with original timing");
+                            1.0
+                        }
+                        Err(e) => {
+                            error!("Invalid replay speed: {e}, replaying with original timing");
+                            1.0
+                        }
+                    };
+                    imp.replay(&path, speed);
+                    return -1;
+                }
+
+                if let Ok(path) = opts.lookup::<PathBuf>("record") {
+                    imp.record_path.replace(path);
+                }
+
                 match opts.lookup::<String>("socket") {
-                    Ok(p) => {
-                        current_remote_label.set_label(p.as_deref().unwrap_or(DEFAULT_REMOTE_NAME));
-                        pw_sender.send(GtkMessage::Connect(p)).unwrap();
-                    },
+                    Ok(p) => imp.connect_to_remote(p),
                     Err(e) => error!("Invalid socket path: {e}"),
                 }
                 -1
-            }));
+            });
         }
     }
 }
@@ -171,27 +661,48 @@ glib::wrapper! {
 impl Application {
     /// Create the view.
     /// This will set up the entire user interface and prepare it for being run.
-    pub(super) fn new(
-        gtk_receiver: async_channel::Receiver<PipewireMessage>,
-        pw_sender: Sender<GtkMessage>,
-    ) -> Self {
+    pub(super) fn new() -> Self {
         let app: Application = glib::Object::builder()
             .property("application-id", APP_ID)
             .build();
 
         let imp = app.imp();
 
-        imp.setup_options(pw_sender.clone());
+        imp.setup_options();
 
-        imp.graph_manager
-            .set(GraphManager::new(
-                &imp.window.graph(),
-                &imp.window.connection_banner(),
-                pw_sender,
-                gtk_receiver,
-            ))
-            .expect("Should be able to set graph manager");
+        imp.settings
+            .set(load_settings())
+            .expect("Should be able to set settings");
 
         app
     }
 }
+
+/// Load the settings from the installed schema.
+///
+/// When running from a checkout, the schema is usually not installed.
+/// The schema compiled by the build script is used instead, and changes are kept in memory only.
+fn load_settings() -> gio::Settings {
+    let installed =
+        gio::SettingsSchemaSource::default().and_then(|source| source.lookup(APP_ID, true));
+    if let Some(schema) = installed {
+        return gio::Settings::new_full(&schema, None::<&gio::SettingsBackend>, None);
+    }
+
+    let built = option_env!("HELVUM_SCHEMA_DIR").and_then(|dir| {
+        match gio::SettingsSchemaSource::from_directory(dir, None, false) {
+            Ok(source) => source.lookup(APP_ID, false),
+            Err(e) => {
+                error!("Failed to load settings schema from {dir}: {e}");
+                None
+            }
+        }
+    });
+    let Some(schema) = built else {
+        error!("Settings schema {APP_ID} is neither installed nor compiled by the build script");
+        std::process::exit(1);
+    };
+
+    warn!("Settings schema {APP_ID} is not installed, settings will not be saved");
+    gio::Settings::new_full(&schema, Some(&gio::memory_settings_backend_new()), None)
+}
@@ -0,0 +1,48 @@
+// Copyright 2021 Tom A. Wagner <tom.a.wagner@protonmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License version 3 as published by
+// the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Backends that the GTK side of the application talks to.
+//!
+//! The GTK side sends [`GtkMessage`]s to a backend, which notifies it about changes
+//! to the graph by sending [`PipewireMessage`]s through an [`async_channel`].
+
+mod scripted;
+pub use scripted::ScriptedBackend;
+#[cfg(feature = "benchmark")]
+mod synthetic;
+#[cfg(feature = "benchmark")]
+pub use synthetic::synthetic_graph;
+mod trace;
+pub use trace::*;
+
+use crate::GtkMessage;
+
+/// The other end of the message protocol, providing the graph shown by the GTK side.
+///
+/// The messages sent by the backend are received from the channel that was returned when the backend was created.
+pub trait Backend {
+    /// Send a message to the backend.
+    fn send(&self, msg: GtkMessage);
+
+    /// Ask the backend to shut down, without waiting for it to finish.
+    fn terminate(&self);
+
+    /// Wait until the backend finished shutting down after [`Backend::terminate`].
+    ///
+    /// This may block for as long as the backend takes to finish, so it should only be called
+    /// once the application exits. No more messages are sent by the backend once this returns.
+    fn join(&self) {}
+}
@@ -0,0 +1,212 @@
+// Copyright 2021 Tom A. Wagner <tom.a.wagner@protonmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License version 3 as published by
+// the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+use adw::glib;
+use log::{debug, warn};
+
+use super::Backend;
+use crate::{GtkMessage, PipewireMessage};
+
+/// An in-memory backend that sends a scripted sequence of messages instead of talking to a pipewire server.
+///
+/// Messages sent to the backend are logged, but not acted upon.
+pub struct ScriptedBackend {
+    sender: RefCell<Option<async_channel::Sender<PipewireMessage>>>,
+    /// The task sending the messages of a timed script, see [`ScriptedBackend::timed`].
+    task: RefCell<Option<glib::JoinHandle<()>>>,
+}
+
+impl ScriptedBackend {
+    /// Create a new backend that sends the messages of the script in order.
+    ///
+    /// Returns the backend and the receiver of the messages sent by it.
+    pub fn new(
+        script: impl IntoIterator<Item = PipewireMessage>,
+    ) -> (Self, async_channel::Receiver<PipewireMessage>) {
+        let (sender, receiver) = async_channel::unbounded();
+
+        let backend = Self {
+            sender: RefCell::new(Some(sender)),
+            task: RefCell::new(None),
+        };
+        for msg in script {
+            backend.push(msg);
+        }
+
+        (backend, receiver)
+    }
+
+    /// Create a new backend that sends each message of the script once the time it is scheduled for,
+    /// relative to the creation of the backend, has passed.
+    ///
+    /// This reproduces the timing of the messages of a recorded trace, sped up by the factor `speed`.
+    /// A speed of zero sends all messages as fast as possible, keeping only their order.
+    ///
+    /// Returns the backend and the receiver of the messages sent by it.
+    pub fn timed(
+        script: Vec<(Duration, PipewireMessage)>,
+        speed: f64,
+    ) -> (Self, async_channel::Receiver<PipewireMessage>) {
+        let (backend, receiver) = Self::new([]);
+        let sender = backend
+            .sender
+            .borrow()
+            .clone()
+            .expect("New backend should not be terminated");
+
+        let task = glib::MainContext::ref_thread_default().spawn_local(async move {
+            let start = Instant::now();
+            for (time, msg) in script {
+                let time = if speed > 0.0 {
+                    time.div_f64(speed)
+                } else {
+                    Duration::ZERO
+                };
+                let delay = time.saturating_sub(start.elapsed());
+                if !delay.is_zero() {
+                    glib::timeout_future(delay).await;
+                }
+
+                if sender.send(msg).await.is_err() {
+                    warn!("Failed to send message from scripted backend, receiver is gone");
+                    return;
+                }
+            }
+        });
+        backend.task.replace(Some(task));
+
+        (backend, receiver)
+    }
+
+    /// Send another message, in addition to the ones from the script.
+    pub fn push(&self, msg: PipewireMessage) {
+        let sender = self.sender.borrow();
+        let Some(sender) = sender.as_ref() else {
+            warn!("Tried to send message from terminated scripted backend");
+            return;
+        };
+
+        if sender.try_send(msg).is_err() {
+            warn!("Failed to send message from scripted backend, receiver is gone");
+        }
+    }
+}
+
+impl Backend for ScriptedBackend {
+    fn send(&self, msg: GtkMessage) {
+        debug!("Scripted backend received message: {msg:?}");
+
+        if let GtkMessage::Terminate = msg {
+            self.terminate();
+        }
+    }
+
+    fn terminate(&self) {
+        // Messages of a timed script that are still scheduled are not sent anymore.
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        // Dropping the sender closes the channel once all pending messages are received.
+        self.sender.replace(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Receive the specified number of messages from the receiver, iterating the thread default main context.
+    fn receive(
+        receiver: &async_channel::Receiver<PipewireMessage>,
+        count: usize,
+    ) -> Vec<PipewireMessage> {
+        glib::MainContext::ref_thread_default().block_on(async {
+            let mut messages = Vec::new();
+            for _ in 0..count {
+                messages.push(
+                    receiver
+                        .recv()
+                        .await
+                        .expect("Backend should not be terminated"),
+                );
+            }
+            messages
+        })
+    }
+
+    #[test]
+    fn timed_script_without_speed_is_sent_at_once() {
+        let context = glib::MainContext::new();
+        context
+            .with_thread_default(|| {
+                let script = vec![
+                    (Duration::from_secs(3600), PipewireMessage::Connected),
+                    (Duration::from_secs(7200), PipewireMessage::Disconnected),
+                ];
+                let (_backend, receiver) = ScriptedBackend::timed(script, 0.0);
+
+                let start = Instant::now();
+                let messages = receive(&receiver, 2);
+
+                assert!(start.elapsed() < Duration::from_secs(60));
+                assert!(matches!(
+                    messages.as_slice(),
+                    [PipewireMessage::Connected, PipewireMessage::Disconnected]
+                ));
+            })
+            .expect("Failed to acquire main context");
+    }
+
+    #[test]
+    fn timed_script_is_sped_up() {
+        let context = glib::MainContext::new();
+        context
+            .with_thread_default(|| {
+                let script = vec![(Duration::from_secs(20), PipewireMessage::Connected)];
+                let (_backend, receiver) = ScriptedBackend::timed(script, 1000.0);
+
+                let start = Instant::now();
+                let messages = receive(&receiver, 1);
+
+                let elapsed = start.elapsed();
+                assert!(elapsed >= Duration::from_millis(20));
+                assert!(elapsed < Duration::from_secs(20));
+                assert!(matches!(messages.as_slice(), [PipewireMessage::Connected]));
+            })
+            .expect("Failed to acquire main context");
+    }
+
+    #[test]
+    fn terminating_stops_timed_script() {
+        let context = glib::MainContext::new();
+        context
+            .with_thread_default(|| {
+                let script = vec![(Duration::from_secs(3600), PipewireMessage::Connected)];
+                let (backend, receiver) = ScriptedBackend::timed(script, 1.0);
+
+                backend.terminate();
+
+                let received = context.block_on(receiver.recv());
+                assert!(received.is_err());
+            })
+            .expect("Failed to acquire main context");
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2021 Tom A. Wagner <tom.a.wagner@protonmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License version 3 as published by
+// the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! A generated graph for measuring how the GTK side performs with large graphs.
+
+use pipewire::spa::utils::Direction;
+
+use crate::{LinkState, MediaType, NodeType, PipewireMessage};
+
+/// Number of ports on each node of the synthetic graph.
+const PORTS_PER_NODE: u32 = 8;
+
+/// Generate messages describing a graph with the specified number of links.
+///
+/// The graph consists of output nodes whose ports are each linked to a port of one of the input nodes.
+/// Which ports are linked is chosen pseudo-randomly, but is the same every time,
+/// so that measurements with the same number of links can be compared.
+pub fn synthetic_graph(link_count: u32) -> Vec<PipewireMessage> {
+    let node_count = link_count.div_ceil(PORTS_PER_NODE).max(1);
+    let mut messages = Vec::new();
+    let mut next_id = 0;
+    let mut new_id = || {
+        next_id += 1;
+        next_id
+    };
+
+    let mut add_nodes = |node_type: NodeType, direction: Direction, messages: &mut Vec<_>| {
+        let mut ports = Vec::new();
+        for node in 0..node_count {
+            let node_id = new_id();
+            let name = match node_type {
+                NodeType::Output => format!("Synthetic Source {node}"),
+                NodeType::Input => format!("Synthetic Sink {node}"),
+            };
+            messages.push(PipewireMessage::NodeAdded {
+                id: node_id,
+                identity: format!("synthetic.node.{node_id}"),
+                name,
+                node_type: Some(node_type.clone()),
+                media_class: None,
+                is_virtual: false,
+            });
+
+            for port in 0..PORTS_PER_NODE {
+                let port_id = new_id();
+                messages.push(PipewireMessage::PortAdded {
+                    id: port_id,
+                    node_id,
+                    name: format!("port_{port}"),
+                    direction,
+                    is_monitor: false,
+                    is_midi: false,
+                    alias: None,
+                    port_index: Some(port),
+                    audio_channel: None,
+                    object_path: None,
+                    is_physical: false,
+                    is_terminal: false,
+                });
+                messages.push(PipewireMessage::PortFormatChanged {
+                    id: port_id,
+                    media_type: MediaType::Audio,
+                    format: None,
+                });
+                ports.push(port_id);
+            }
+        }
+        ports
+    };
+
+    let output_ports = add_nodes(NodeType::Output, Direction::Output, &mut messages);
+    let input_ports = add_nodes(NodeType::Input, Direction::Input, &mut messages);
+
+    // A simple linear congruential generator is good enough to spread the links.
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    for &port_from in output_ports.iter().take(link_count as usize) {
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let port_to = input_ports[(seed >> 33) as usize % input_ports.len()];
+
+        messages.push(PipewireMessage::LinkAdded {
+            id: new_id(),
+            port_from,
+            port_to,
+            state: LinkState::Active,
+            error: None,
+            passive: false,
+            linger: true,
+            created_by_helvum: false,
+            media_type: MediaType::Audio,
+        });
+    }
+
+    messages
+}
@@ -0,0 +1,635 @@
+// Copyright 2021 Tom A. Wagner <tom.a.wagner@protonmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License version 3 as published by
+// the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Recording of the messages sent by a backend to a trace file, so that they can be replayed later.
+//!
+//! A trace file contains one message per line in the GVariant text format,
+//! as a tuple of the time since the start of the recording in microseconds, the kind of message
+//! and a dictionary of its fields.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use adw::glib::{self, FromVariant, ToVariant, VariantDict};
+use pipewire::spa::utils::Direction;
+
+use crate::{LinkState, MediaType, NodeType, PipewireMessage};
+
+/// The type of a single line in a trace file.
+const LINE_TYPE: &str = "(tsa{sv})";
+
+/// Writes messages to a trace file as they are received.
+pub struct TraceWriter {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl TraceWriter {
+    /// Create a new trace file at the specified path, replacing any existing file.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+        })
+    }
+
+    /// Append the message to the trace, timestamped with the time since the trace was created.
+    pub fn record(&mut self, msg: &PipewireMessage) -> io::Result<()> {
+        // A snapshot is recorded as the messages it consists of, which have the same effect when replayed.
+        if let PipewireMessage::Snapshot(messages) = msg {
+            for msg in messages {
+                self.record(msg)?;
+            }
+            return Ok(());
+        }
+
+        let timestamp = u64::try_from(self.start.elapsed().as_micros()).unwrap_or(u64::MAX);
+        let (kind, fields) = encode(msg);
+        // Types are annotated, so that numbers in the fields are parsed back with the same type.
+        let line = (timestamp, kind.to_string(), fields)
+            .to_variant()
+            .print(true);
+
+        writeln!(self.file, "{line}")?;
+        // Flush every message, so that the trace is complete even if the application crashes.
+        self.file.flush()
+    }
+}
+
+/// Read all messages from a trace file, together with the time they were recorded at.
+pub fn read_trace(path: &Path) -> io::Result<Vec<(Duration, PipewireMessage)>> {
+    let file = BufReader::new(File::open(path)?);
+    let line_type = glib::VariantTy::new(LINE_TYPE).expect("Line type should be valid");
+
+    let mut messages = Vec::new();
+    for (line_number, line) in file.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let invalid_line = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid message on line {}: {reason}", line_number + 1),
+            )
+        };
+
+        let variant = glib::Variant::parse(Some(line_type), &line)
+            .map_err(|e| invalid_line(e.to_string()))?;
+        let (timestamp, kind, fields) = <(u64, String, VariantDict)>::from_variant(&variant)
+            .ok_or_else(|| invalid_line(format!("Expected {LINE_TYPE}")))?;
+        let msg = decode(&kind, &fields).map_err(invalid_line)?;
+
+        messages.push((Duration::from_micros(timestamp), msg));
+    }
+
+    Ok(messages)
+}
+
+/// Convert a message to its kind and a dictionary of its fields.
+fn encode(msg: &PipewireMessage) -> (&'static str, VariantDict) {
+    let fields = VariantDict::new(None);
+
+    let kind = match msg {
+        PipewireMessage::NodeAdded {
+            id,
+            identity,
+            name,
+            node_type,
+            media_class,
+            is_virtual,
+        } => {
+            fields.insert_value("id", &id.to_variant());
+            fields.insert_value("identity", &identity.to_variant());
+            fields.insert_value("name", &name.to_variant());
+            match node_type {
+                Some(NodeType::Input) => fields.insert_value("node-type", &"input".to_variant()),
+                Some(NodeType::Output) => fields.insert_value("node-type", &"output".to_variant()),
+                None => {}
+            }
+            if let Some(media_class) = media_class {
+                fields.insert_value("media-class", &media_class.to_variant());
+            }
+            fields.insert_value("is-virtual", &is_virtual.to_variant());
+            "NodeAdded"
+        }
+        PipewireMessage::NodeNameChanged {
+            id,
+            name,
+            media_name,
+        } => {
+            fields.insert_value("id", &id.to_variant());
+            fields.insert_value("name", &name.to_variant());
+            fields.insert_value("media-name", &media_name.to_variant());
+            "NodeNameChanged"
+        }
+        PipewireMessage::NodeTargetChanged { id, target } => {
+            fields.insert_value("id", &id.to_variant());
+            if let Some(target) = target {
+                fields.insert_value("target", &target.to_variant());
+            }
+            "NodeTargetChanged"
+        }
+        PipewireMessage::PortAdded {
+            id,
+            node_id,
+            name,
+            direction,
+            is_monitor,
+            is_midi,
+            alias,
+            port_index,
+            audio_channel,
+            object_path,
+            is_physical,
+            is_terminal,
+        } => {
+            fields.insert_value("id", &id.to_variant());
+            fields.insert_value("node-id", &node_id.to_variant());
+            fields.insert_value("name", &name.to_variant());
+            fields.insert_value("direction", &direction.as_raw().to_variant());
+            fields.insert_value("is-monitor", &is_monitor.to_variant());
+            fields.insert_value("is-midi", &is_midi.to_variant());
+            if let Some(alias) = alias {
+                fields.insert_value("alias", &alias.to_variant());
+            }
+            if let Some(port_index) = port_index {
+                fields.insert_value("port-index", &port_index.to_variant());
+            }
+            if let Some(audio_channel) = audio_channel {
+                fields.insert_value("audio-channel", &audio_channel.to_variant());
+            }
+            if let Some(object_path) = object_path {
+                fields.insert_value("object-path", &object_path.to_variant());
+            }
+            fields.insert_value("is-physical", &is_physical.to_variant());
+            fields.insert_value("is-terminal", &is_terminal.to_variant());
+            "PortAdded"
+        }
+        PipewireMessage::PortFormatChanged {
+            id,
+            media_type,
+            format,
+        } => {
+            fields.insert_value("id", &id.to_variant());
+            fields.insert_value("media-type", &media_type.as_raw().to_variant());
+            if let Some(format) = format {
+                fields.insert_value("format", &format.to_variant());
+            }
+            "PortFormatChanged"
+        }
+        PipewireMessage::LinkAdded {
+            id,
+            port_from,
+            port_to,
+            state,
+            error,
+            passive,
+            linger,
+            created_by_helvum,
+            media_type,
+        } => {
+            fields.insert_value("id", &id.to_variant());
+            fields.insert_value("port-from", &port_from.to_variant());
+            fields.insert_value("port-to", &port_to.to_variant());
+            fields.insert_value("state", &link_state_name(*state).to_variant());
+            if let Some(error) = error {
+                fields.insert_value("error", &error.to_variant());
+            }
+            fields.insert_value("passive", &passive.to_variant());
+            fields.insert_value("linger", &linger.to_variant());
+            fields.insert_value("created-by-helvum", &created_by_helvum.to_variant());
+            fields.insert_value("media-type", &media_type.as_raw().to_variant());
+            "LinkAdded"
+        }
+        PipewireMessage::LinkStateChanged { id, state, error } => {
+            fields.insert_value("id", &id.to_variant());
+            fields.insert_value("state", &link_state_name(*state).to_variant());
+            if let Some(error) = error {
+                fields.insert_value("error", &error.to_variant());
+            }
+            "LinkStateChanged"
+        }
+        PipewireMessage::LinkFormatChanged { id, media_type } => {
+            fields.insert_value("id", &id.to_variant());
+            fields.insert_value("media-type", &media_type.as_raw().to_variant());
+            "LinkFormatChanged"
+        }
+        PipewireMessage::NodeRemoved { id } => {
+            fields.insert_value("id", &id.to_variant());
+            "NodeRemoved"
+        }
+        PipewireMessage::PortRemoved { id, node_id } => {
+            fields.insert_value("id", &id.to_variant());
+            fields.insert_value("node-id", &node_id.to_variant());
+            "PortRemoved"
+        }
+        PipewireMessage::LinkRemoved { id } => {
+            fields.insert_value("id", &id.to_variant());
+            "LinkRemoved"
+        }
+        PipewireMessage::Connecting {
+            remote,
+            attempt,
+            error,
+            retry_in,
+        } => {
+            if let Some(remote) = remote {
+                fields.insert_value("remote", &remote.to_variant());
+            }
+            fields.insert_value("attempt", &attempt.to_variant());
+            fields.insert_value("error", &error.to_variant());
+            let retry_in = u64::try_from(retry_in.as_millis()).unwrap_or(u64::MAX);
+            fields.insert_value("retry-in", &retry_in.to_variant());
+            "Connecting"
+        }
+        PipewireMessage::Connected => "Connected",
+        PipewireMessage::Disconnected => "Disconnected",
+        PipewireMessage::MalformedObject { id, reason } => {
+            fields.insert_value("id", &id.to_variant());
+            fields.insert_value("reason", &reason.to_variant());
+            "MalformedObject"
+        }
+        PipewireMessage::Snapshot(_) => {
+            unreachable!("Snapshots are recorded as the messages they consist of")
+        }
+    };
+
+    (kind, fields)
+}
+
+/// Get a required field from the fields of a message.
+fn field<T: FromVariant>(fields: &VariantDict, key: &str) -> Result<T, String> {
+    fields
+        .lookup::<T>(key)
+        .ok()
+        .flatten()
+        .ok_or_else(|| format!("Missing or invalid field \"{key}\""))
+}
+
+/// Get an optional field from the fields of a message.
+fn optional_field<T: FromVariant>(fields: &VariantDict, key: &str) -> Result<Option<T>, String> {
+    fields
+        .lookup::<T>(key)
+        .map_err(|_| format!("Invalid field \"{key}\""))
+}
+
+/// Get the name that a link state is stored as.
+fn link_state_name(state: LinkState) -> &'static str {
+    match state {
+        LinkState::Error => "error",
+        LinkState::Unlinked => "unlinked",
+        LinkState::Init => "init",
+        LinkState::Negotiating => "negotiating",
+        LinkState::Allocating => "allocating",
+        LinkState::Paused => "paused",
+        LinkState::Active => "active",
+    }
+}
+
+/// Get the state of a link from the fields of a message.
+fn link_state_field(fields: &VariantDict) -> Result<LinkState, String> {
+    let state = match field::<String>(fields, "state")?.as_str() {
+        "error" => LinkState::Error,
+        "unlinked" => LinkState::Unlinked,
+        "init" => LinkState::Init,
+        "negotiating" => LinkState::Negotiating,
+        "allocating" => LinkState::Allocating,
+        "paused" => LinkState::Paused,
+        "active" => LinkState::Active,
+        other => return Err(format!("Unknown link state \"{other}\"")),
+    };
+
+    Ok(state)
+}
+
+/// Convert the kind of a message and a dictionary of its fields back to the message.
+fn decode(kind: &str, fields: &VariantDict) -> Result<PipewireMessage, String> {
+    let msg = match kind {
+        "NodeAdded" => PipewireMessage::NodeAdded {
+            id: field(fields, "id")?,
+            identity: field(fields, "identity")?,
+            name: field(fields, "name")?,
+            node_type: match optional_field::<String>(fields, "node-type")?.as_deref() {
+                Some("input") => Some(NodeType::Input),
+                Some("output") => Some(NodeType::Output),
+                Some(other) => return Err(format!("Unknown node type \"{other}\"")),
+                None => None,
+            },
+            media_class: optional_field(fields, "media-class")?,
+            is_virtual: field(fields, "is-virtual")?,
+        },
+        "NodeNameChanged" => PipewireMessage::NodeNameChanged {
+            id: field(fields, "id")?,
+            name: field(fields, "name")?,
+            media_name: field(fields, "media-name")?,
+        },
+        "NodeTargetChanged" => PipewireMessage::NodeTargetChanged {
+            id: field(fields, "id")?,
+            target: optional_field(fields, "target")?,
+        },
+        "PortAdded" => PipewireMessage::PortAdded {
+            id: field(fields, "id")?,
+            node_id: field(fields, "node-id")?,
+            name: field(fields, "name")?,
+            direction: Direction::from_raw(field(fields, "direction")?),
+            is_monitor: field(fields, "is-monitor")?,
+            is_midi: field(fields, "is-midi")?,
+            alias: optional_field(fields, "alias")?,
+            port_index: optional_field(fields, "port-index")?,
+            audio_channel: optional_field(fields, "audio-channel")?,
+            object_path: optional_field(fields, "object-path")?,
+            is_physical: field(fields, "is-physical")?,
+            is_terminal: field(fields, "is-terminal")?,
+        },
+        "PortFormatChanged" => PipewireMessage::PortFormatChanged {
+            id: field(fields, "id")?,
+            media_type: MediaType::from_raw(field(fields, "media-type")?),
+            format: optional_field(fields, "format")?,
+        },
+        "LinkAdded" => PipewireMessage::LinkAdded {
+            id: field(fields, "id")?,
+            port_from: field(fields, "port-from")?,
+            port_to: field(fields, "port-to")?,
+            state: link_state_field(fields)?,
+            error: optional_field(fields, "error")?,
+            passive: field(fields, "passive")?,
+            linger: field(fields, "linger")?,
+            created_by_helvum: field(fields, "created-by-helvum")?,
+            media_type: MediaType::from_raw(field(fields, "media-type")?),
+        },
+        "LinkStateChanged" => PipewireMessage::LinkStateChanged {
+            id: field(fields, "id")?,
+            state: link_state_field(fields)?,
+            error: optional_field(fields, "error")?,
+        },
+        "LinkFormatChanged" => PipewireMessage::LinkFormatChanged {
+            id: field(fields, "id")?,
+            media_type: MediaType::from_raw(field(fields, "media-type")?),
+        },
+        "NodeRemoved" => PipewireMessage::NodeRemoved {
+            id: field(fields, "id")?,
+        },
+        "PortRemoved" => PipewireMessage::PortRemoved {
+            id: field(fields, "id")?,
+            node_id: field(fields, "node-id")?,
+        },
+        "LinkRemoved" => PipewireMessage::LinkRemoved {
+            id: field(fields, "id")?,
+        },
+        "Connecting" => PipewireMessage::Connecting {
+            remote: optional_field(fields, "remote")?,
+            attempt: field(fields, "attempt")?,
+            error: field(fields, "error")?,
+            retry_in: Duration::from_millis(field(fields, "retry-in")?),
+        },
+        "Connected" => PipewireMessage::Connected,
+        "Disconnected" => PipewireMessage::Disconnected,
+        "MalformedObject" => PipewireMessage::MalformedObject {
+            id: field(fields, "id")?,
+            reason: field(fields, "reason")?,
+        },
+        other => return Err(format!("Unknown message kind \"{other}\"")),
+    };
+
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A message of every kind, with optional fields both set and unset.
+    fn messages() -> Vec<PipewireMessage> {
+        vec![
+            PipewireMessage::NodeAdded {
+                id: 1,
+                identity: "alsa_output.pci-0000_00_1f.3".to_string(),
+                name: "Built-in Audio".to_string(),
+                node_type: Some(NodeType::Input),
+                media_class: Some("Audio/Sink".to_string()),
+                is_virtual: false,
+            },
+            PipewireMessage::NodeAdded {
+                id: 2,
+                identity: String::new(),
+                name: "Bridge".to_string(),
+                node_type: None,
+                media_class: None,
+                is_virtual: true,
+            },
+            PipewireMessage::NodeNameChanged {
+                id: 1,
+                name: "Speakers".to_string(),
+                media_name: "Music".to_string(),
+            },
+            PipewireMessage::NodeTargetChanged {
+                id: 1,
+                target: Some("Speakers".to_string()),
+            },
+            PipewireMessage::NodeTargetChanged {
+                id: 1,
+                target: None,
+            },
+            PipewireMessage::PortAdded {
+                id: 3,
+                node_id: 1,
+                name: "playback_FL".to_string(),
+                direction: Direction::Input,
+                is_monitor: false,
+                is_midi: false,
+                alias: Some("Speakers:playback_FL".to_string()),
+                port_index: Some(0),
+                audio_channel: Some("FL".to_string()),
+                object_path: Some("alsa:pcm:0:front:0:playback:0".to_string()),
+                is_physical: true,
+                is_terminal: true,
+            },
+            PipewireMessage::PortAdded {
+                id: 4,
+                node_id: 2,
+                name: "midi_out".to_string(),
+                direction: Direction::Output,
+                is_monitor: true,
+                is_midi: true,
+                alias: None,
+                port_index: None,
+                audio_channel: None,
+                object_path: None,
+                is_physical: false,
+                is_terminal: false,
+            },
+            PipewireMessage::PortFormatChanged {
+                id: 3,
+                media_type: MediaType::Audio,
+                format: Some("F32LE, 48000 Hz, 2 channels".to_string()),
+            },
+            PipewireMessage::PortFormatChanged {
+                id: 4,
+                media_type: MediaType::Application,
+                format: None,
+            },
+            PipewireMessage::LinkAdded {
+                id: 5,
+                port_from: 4,
+                port_to: 3,
+                state: LinkState::Error,
+                error: Some("no more input formats".to_string()),
+                passive: true,
+                linger: false,
+                created_by_helvum: true,
+                media_type: MediaType::Audio,
+            },
+            PipewireMessage::LinkAdded {
+                id: 6,
+                port_from: 4,
+                port_to: 3,
+                state: LinkState::Init,
+                error: None,
+                passive: false,
+                linger: true,
+                created_by_helvum: false,
+                media_type: MediaType::Unknown,
+            },
+            PipewireMessage::LinkStateChanged {
+                id: 5,
+                state: LinkState::Active,
+                error: None,
+            },
+            PipewireMessage::LinkStateChanged {
+                id: 6,
+                state: LinkState::Error,
+                error: Some("negotiation failed".to_string()),
+            },
+            PipewireMessage::LinkFormatChanged {
+                id: 5,
+                media_type: MediaType::Video,
+            },
+            PipewireMessage::LinkRemoved { id: 5 },
+            PipewireMessage::PortRemoved { id: 3, node_id: 1 },
+            PipewireMessage::NodeRemoved { id: 1 },
+            PipewireMessage::Connecting {
+                remote: Some("pipewire-1".to_string()),
+                attempt: 3,
+                error: "Connection refused".to_string(),
+                retry_in: Duration::from_millis(1600),
+            },
+            PipewireMessage::Connecting {
+                remote: None,
+                attempt: 1,
+                error: String::new(),
+                retry_in: Duration::ZERO,
+            },
+            PipewireMessage::Connected,
+            PipewireMessage::Disconnected,
+            PipewireMessage::MalformedObject {
+                id: 7,
+                reason: "Port has no node.id property".to_string(),
+            },
+        ]
+    }
+
+    /// Get the index of the kind of the message.
+    ///
+    /// This fails to compile once a new kind of message is added, as a reminder to add it to [`messages`].
+    fn kind_index(msg: &PipewireMessage) -> usize {
+        match msg {
+            PipewireMessage::NodeAdded { .. } => 0,
+            PipewireMessage::NodeNameChanged { .. } => 1,
+            PipewireMessage::NodeTargetChanged { .. } => 2,
+            PipewireMessage::PortAdded { .. } => 3,
+            PipewireMessage::PortFormatChanged { .. } => 4,
+            PipewireMessage::LinkAdded { .. } => 5,
+            PipewireMessage::LinkStateChanged { .. } => 6,
+            PipewireMessage::LinkFormatChanged { .. } => 7,
+            PipewireMessage::NodeRemoved { .. } => 8,
+            PipewireMessage::PortRemoved { .. } => 9,
+            PipewireMessage::LinkRemoved { .. } => 10,
+            PipewireMessage::Connecting { .. } => 11,
+            PipewireMessage::Connected => 12,
+            PipewireMessage::Disconnected => 13,
+            PipewireMessage::MalformedObject { .. } => 14,
+            PipewireMessage::Snapshot(_) => 15,
+        }
+    }
+
+    /// Record the messages to a trace file, and read them back.
+    fn round_trip(messages: &[PipewireMessage], name: &str) -> Vec<(Duration, PipewireMessage)> {
+        let path = std::env::temp_dir().join(format!("helvum-{name}-{}.trace", std::process::id()));
+
+        let mut writer = TraceWriter::create(&path).expect("Failed to create trace");
+        for msg in messages {
+            writer.record(msg).expect("Failed to record message");
+        }
+        drop(writer);
+
+        let trace = read_trace(&path);
+        let _ = std::fs::remove_file(&path);
+        trace.expect("Failed to read trace")
+    }
+
+    #[test]
+    fn every_message_kind_is_covered() {
+        let mut covered: Vec<usize> = messages().iter().map(kind_index).collect();
+        covered.push(kind_index(&PipewireMessage::Snapshot(Vec::new())));
+        covered.sort_unstable();
+        covered.dedup();
+
+        assert_eq!(covered, (0..=15).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn messages_survive_round_trip() {
+        let messages = messages();
+        let trace = round_trip(&messages, "round-trip");
+
+        assert_eq!(trace.len(), messages.len());
+        for ((_, read), written) in trace.iter().zip(&messages) {
+            assert_eq!(format!("{read:?}"), format!("{written:?}"));
+        }
+
+        // Messages are timestamped in the order they were recorded.
+        assert!(trace.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    }
+
+    #[test]
+    fn snapshot_is_recorded_as_its_messages() {
+        let messages = messages();
+        let trace = round_trip(&[PipewireMessage::Snapshot(messages.clone())], "snapshot");
+
+        assert_eq!(trace.len(), messages.len());
+        for ((_, read), written) in trace.iter().zip(&messages) {
+            assert_eq!(format!("{read:?}"), format!("{written:?}"));
+        }
+    }
+
+    #[test]
+    fn missing_fields_are_rejected() {
+        let fields = VariantDict::new(None);
+        fields.insert_value("id", &1u32.to_variant());
+        fields.insert_value("name", &"Node".to_variant());
+        fields.insert_value("is-virtual", &false.to_variant());
+
+        // The identity of the node is missing.
+        assert!(decode("NodeAdded", &fields).is_err());
+        assert!(decode("Unknown", &fields).is_err());
+    }
+}
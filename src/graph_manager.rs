@@ -14,18 +14,200 @@
 //
 // SPDX-License-Identifier: GPL-3.0-only
 
-use adw::{glib, prelude::*, subclass::prelude::*};
+use std::rc::Rc;
 
-use pipewire::channel::Sender as PwSender;
+use adw::{gio, glib, prelude::*, subclass::prelude::*};
 
-use crate::{ui::graph::GraphView, GtkMessage, PipewireMessage};
+use pipewire::spa::utils::Direction;
+
+use crate::{
+    backend::{Backend, TraceWriter},
+    ui::graph::{GraphView, LinkStyle, PortLabelMode, PortSortMode},
+    GtkMessage, LinkOptions, LinkState, MediaType, NodeType, PipewireMessage,
+};
+
+/// Settings keys that affect which objects are shown in the graph.
+const FILTER_KEYS: &[&str] = &[
+    "show-audio",
+    "show-video",
+    "show-midi",
+    "show-virtual-nodes",
+    "show-monitor-ports",
+];
+
+/// Commonly used media classes that can be hidden from the view.
+pub const MEDIA_CLASSES: &[&str] = &[
+    "Audio/Sink",
+    "Audio/Source",
+    "Audio/Duplex",
+    "Stream/Output/Audio",
+    "Stream/Input/Audio",
+    "Video/Source",
+    "Video/Sink",
+    "Stream/Output/Video",
+    "Stream/Input/Video",
+    "Midi/Bridge",
+];
+
+/// Get the settings key that stores whether nodes of the media class are shown, e.g. `show-media-class-audio-sink`.
+pub fn media_class_key(media_class: &str) -> String {
+    format!(
+        "show-media-class-{}",
+        media_class.replace('/', "-").to_lowercase()
+    )
+}
+
+/// Everything we know about a node, so that its widget can be (re-)created at any time.
+struct NodeInfo {
+    /// Name of the node that stays the same when it is recreated, under which its custom label is stored.
+    identity: String,
+    name: String,
+    media_name: String,
+    node_type: Option<NodeType>,
+    media_class: Option<String>,
+    is_virtual: bool,
+}
+
+/// Everything we know about a port, so that its widget can be (re-)created at any time.
+struct PortInfo {
+    node_id: u32,
+    name: String,
+    direction: Direction,
+    media_type: MediaType,
+    is_monitor: bool,
+    is_midi: bool,
+    alias: Option<String>,
+    port_index: Option<u32>,
+    audio_channel: Option<String>,
+    object_path: Option<String>,
+    is_physical: bool,
+    is_terminal: bool,
+    /// Description of the negotiated format of the port, if known.
+    format: Option<String>,
+}
+
+/// Everything we know about a link, so that its widget can be (re-)created at any time.
+struct LinkInfo {
+    port_from: u32,
+    port_to: u32,
+    state: LinkState,
+    /// Why the link failed, if it did.
+    error: Option<String>,
+    passive: bool,
+    linger: bool,
+    created_by_helvum: bool,
+    media_type: MediaType,
+}
+
+/// Rules set by the user that decide which nodes and ports are shown in the graph.
+#[derive(Default)]
+struct ViewFilter {
+    show_audio: bool,
+    show_video: bool,
+    show_midi: bool,
+    show_virtual_nodes: bool,
+    show_monitor_ports: bool,
+    hidden_media_classes: Vec<&'static str>,
+}
+
+impl ViewFilter {
+    fn from_settings(settings: &gio::Settings) -> Self {
+        Self {
+            show_audio: settings.boolean("show-audio"),
+            show_video: settings.boolean("show-video"),
+            show_midi: settings.boolean("show-midi"),
+            show_virtual_nodes: settings.boolean("show-virtual-nodes"),
+            show_monitor_ports: settings.boolean("show-monitor-ports"),
+            hidden_media_classes: MEDIA_CLASSES
+                .iter()
+                .copied()
+                .filter(|media_class| !settings.boolean(&media_class_key(media_class)))
+                .collect(),
+        }
+    }
+
+    fn shows_media_type(&self, media_type: MediaType) -> bool {
+        match media_type {
+            MediaType::Audio => self.show_audio,
+            MediaType::Video => self.show_video,
+            _ => true,
+        }
+    }
+
+    fn is_node_visible(&self, node: &NodeInfo) -> bool {
+        if node.is_virtual && !self.show_virtual_nodes {
+            return false;
+        }
+
+        let Some(media_class) = node.media_class.as_deref() else {
+            return true;
+        };
+
+        if self.hidden_media_classes.contains(&media_class) {
+            return false;
+        }
+
+        if media_class.contains("Midi") || media_class.contains("MIDI") {
+            self.show_midi
+        } else if media_class.contains("Audio") {
+            self.show_audio
+        } else if media_class.contains("Video") {
+            self.show_video
+        } else {
+            true
+        }
+    }
+
+    fn is_port_visible(&self, port: &PortInfo) -> bool {
+        if port.is_monitor && !self.show_monitor_ports {
+            return false;
+        }
+
+        // MIDI is carried as control data, so it can't be told apart by the media type of the port.
+        if port.is_midi {
+            self.show_midi
+        } else {
+            self.shows_media_type(port.media_type)
+        }
+    }
+}
+
+/// Read the order that ports should be shown in from the settings.
+fn port_sort_mode_from_settings(settings: &gio::Settings) -> PortSortMode {
+    match settings.string("port-sort-mode").as_str() {
+        "port-id" => PortSortMode::PortId,
+        "channel-position" => PortSortMode::ChannelPosition,
+        _ => PortSortMode::Natural,
+    }
+}
+
+/// Read which name of ports should be shown from the settings.
+fn port_label_mode_from_settings(settings: &gio::Settings) -> PortLabelMode {
+    match settings.string("port-label-mode").as_str() {
+        "alias" => PortLabelMode::Alias,
+        "audio-channel" => PortLabelMode::Channel,
+        _ => PortLabelMode::Name,
+    }
+}
+
+/// Read how the lines of links should be routed from the settings.
+fn link_style_from_settings(settings: &gio::Settings) -> LinkStyle {
+    match settings.string("link-style").as_str() {
+        "orthogonal" => LinkStyle::Orthogonal,
+        "straight" => LinkStyle::Straight,
+        _ => LinkStyle::Bezier,
+    }
+}
 
 mod imp {
     use super::*;
 
-    use std::{cell::OnceCell, cell::RefCell, collections::HashMap};
+    use std::{
+        cell::{Cell, OnceCell, RefCell},
+        collections::HashMap,
+    };
 
-    use crate::{ui::graph, MediaType, NodeType};
+    use crate::ui::graph;
 
     #[derive(Default, glib::Properties)]
     #[properties(wrapper_type = super::GraphManager)]
@@ -36,8 +218,35 @@ mod imp {
         #[property(get, set, construct_only)]
         pub connection_banner: OnceCell<adw::Banner>,
 
-        pub pw_sender: OnceCell<PwSender<crate::GtkMessage>>,
+        #[property(get, set, construct_only)]
+        pub toast_overlay: OnceCell<adw::ToastOverlay>,
+
+        #[property(get, set, construct_only)]
+        pub settings: OnceCell<gio::Settings>,
+
+        pub backend: OnceCell<Rc<dyn Backend>>,
+        /// If set, all received messages are recorded to this trace.
+        pub recorder: RefCell<Option<TraceWriter>>,
+        /// Widgets of all objects that are currently shown in the graph.
         pub items: RefCell<HashMap<u32, glib::Object>>,
+        /// Port widgets that still need to be added to their node.
+        pending_ports: RefCell<Vec<(graph::Node, graph::Port)>>,
+        /// The toast telling the user about ignored malformed objects while it is shown,
+        /// together with the number of objects it tells about.
+        malformed_toast: RefCell<Option<(adw::Toast, u32)>>,
+
+        // Information on all known objects, including those hidden by the view filter.
+        nodes: RefCell<HashMap<u32, NodeInfo>>,
+        ports: RefCell<HashMap<u32, PortInfo>>,
+        links: RefCell<HashMap<u32, LinkInfo>>,
+        /// Names of the nodes that stream nodes are pinned to by the session manager.
+        ///
+        /// These are kept separately from the node info, as the metadata may be received before the node.
+        node_targets: RefCell<HashMap<u32, String>>,
+
+        filter: RefCell<ViewFilter>,
+        pub(super) port_sort_mode: Cell<graph::PortSortMode>,
+        pub(super) port_label_mode: Cell<graph::PortLabelMode>,
     }
 
     #[glib::object_subclass]
@@ -48,80 +257,341 @@ mod imp {
     }
 
     #[glib::derived_properties]
-    impl ObjectImpl for GraphManager {}
+    impl ObjectImpl for GraphManager {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            let settings = self.obj().settings();
+            self.filter.replace(ViewFilter::from_settings(&settings));
+
+            // The banner is shown while trying to reconnect, its button skips the wait for the next attempt.
+            self.obj().connection_banner().connect_button_clicked(
+                glib::clone!(@weak self as imp => move |_| {
+                    let backend = imp.backend.get().expect("backend should be set");
+                    backend.send(GtkMessage::Retry);
+                }),
+            );
+
+            self.port_sort_mode
+                .set(port_sort_mode_from_settings(&settings));
+            self.port_label_mode
+                .set(port_label_mode_from_settings(&settings));
+            settings.connect_changed(
+                Some("port-sort-mode"),
+                glib::clone!(@weak self as imp => move |settings, _| {
+                    imp.port_sort_mode.set(port_sort_mode_from_settings(settings));
+                    imp.update_port_order();
+                }),
+            );
+            settings.connect_changed(
+                Some("port-label-mode"),
+                glib::clone!(@weak self as imp => move |settings, _| {
+                    imp.port_label_mode.set(port_label_mode_from_settings(settings));
+                    imp.update_port_labels();
+                }),
+            );
+
+            let graph = self.obj().graph();
+            graph.set_link_style(link_style_from_settings(&settings));
+            graph.set_animate_links(settings.boolean("animate-links"));
+            settings.connect_changed(
+                Some("link-style"),
+                glib::clone!(@weak graph => move |settings, _| {
+                    graph.set_link_style(link_style_from_settings(settings));
+                }),
+            );
+            settings.connect_changed(
+                Some("animate-links"),
+                glib::clone!(@weak graph => move |settings, key| {
+                    graph.set_animate_links(settings.boolean(key));
+                }),
+            );
+
+            settings.connect_changed(
+                Some("node-labels"),
+                glib::clone!(@weak self as imp => move |_, _| {
+                    imp.update_node_labels();
+                }),
+            );
+
+            let media_class_keys = MEDIA_CLASSES.iter().map(|class| media_class_key(class));
+            for key in FILTER_KEYS
+                .iter()
+                .map(ToString::to_string)
+                .chain(media_class_keys)
+            {
+                settings.connect_changed(
+                    Some(&key),
+                    glib::clone!(@weak self as imp => move |settings, _| {
+                        imp.filter.replace(ViewFilter::from_settings(settings));
+                        imp.refilter();
+                    }),
+                );
+            }
+        }
+    }
 
     impl GraphManager {
         pub async fn receive(&self, receiver: async_channel::Receiver<crate::PipewireMessage>) {
-            loop {
-                let Ok(msg) = receiver.recv().await else {
-                    continue;
-                };
-                match msg {
-                    PipewireMessage::NodeAdded {
-                        id,
+            // The channel is closed once the pipewire thread finished, e.g. when its tab was closed.
+            while let Ok(msg) = receiver.recv().await {
+                self.handle_message(msg);
+
+                // Handle the messages that arrived in the meantime as well before laying out the ports added by them,
+                // so that many ports appearing at once, e.g. of a new device, are laid out in a single pass.
+                while let Ok(msg) = receiver.try_recv() {
+                    self.handle_message(msg);
+                }
+                self.add_pending_ports();
+            }
+        }
+
+        fn handle_message(&self, msg: PipewireMessage) {
+            self.record(&msg);
+            self.apply_message(msg);
+        }
+
+        /// Update the graph according to the message.
+        fn apply_message(&self, msg: PipewireMessage) {
+            match msg {
+                PipewireMessage::NodeAdded {
+                    id,
+                    identity,
+                    name,
+                    node_type,
+                    media_class,
+                    is_virtual,
+                } => self.add_node(
+                    id,
+                    NodeInfo {
+                        identity,
                         name,
+                        media_name: String::new(),
                         node_type,
-                    } => self.add_node(id, name.as_str(), node_type),
-                    PipewireMessage::NodeNameChanged {
-                        id,
-                        name,
-                        media_name,
-                    } => self.node_name_changed(id, &name, &media_name),
-                    PipewireMessage::PortAdded {
-                        id,
+                        media_class,
+                        is_virtual,
+                    },
+                ),
+                PipewireMessage::NodeNameChanged {
+                    id,
+                    name,
+                    media_name,
+                } => self.node_name_changed(id, name, media_name),
+                PipewireMessage::NodeTargetChanged { id, target } => {
+                    self.node_target_changed(id, target)
+                }
+                PipewireMessage::PortAdded {
+                    id,
+                    node_id,
+                    name,
+                    direction,
+                    is_monitor,
+                    is_midi,
+                    alias,
+                    port_index,
+                    audio_channel,
+                    object_path,
+                    is_physical,
+                    is_terminal,
+                } => self.add_port(
+                    id,
+                    PortInfo {
                         node_id,
                         name,
                         direction,
-                    } => self.add_port(id, name.as_str(), node_id, direction),
-                    PipewireMessage::PortFormatChanged { id, media_type } => {
-                        self.port_media_type_changed(id, media_type)
-                    }
-                    PipewireMessage::LinkAdded {
-                        id,
+                        media_type: MediaType::Unknown,
+                        is_monitor,
+                        is_midi,
+                        alias,
+                        port_index,
+                        audio_channel,
+                        object_path,
+                        is_physical,
+                        is_terminal,
+                        format: None,
+                    },
+                ),
+                PipewireMessage::PortFormatChanged {
+                    id,
+                    media_type,
+                    format,
+                } => self.port_format_changed(id, media_type, format),
+                PipewireMessage::LinkAdded {
+                    id,
+                    port_from,
+                    port_to,
+                    state,
+                    error,
+                    passive,
+                    linger,
+                    created_by_helvum,
+                    media_type,
+                } => self.add_link(
+                    id,
+                    LinkInfo {
                         port_from,
                         port_to,
-                        active,
+                        state,
+                        error,
+                        passive,
+                        linger,
+                        created_by_helvum,
                         media_type,
-                    } => self.add_link(id, port_from, port_to, active, media_type),
-                    PipewireMessage::LinkStateChanged { id, active } => {
-                        self.link_state_changed(id, active)
-                    }
-                    PipewireMessage::LinkFormatChanged { id, media_type } => {
-                        self.link_format_changed(id, media_type)
-                    }
-                    PipewireMessage::NodeRemoved { id } => self.remove_node(id),
-                    PipewireMessage::PortRemoved { id, node_id } => self.remove_port(id, node_id),
-                    PipewireMessage::LinkRemoved { id } => self.remove_link(id),
-                    PipewireMessage::Connecting => {
-                        self.obj().connection_banner().set_revealed(true);
-                    }
-                    PipewireMessage::Connected => {
-                        self.obj().connection_banner().set_revealed(false);
-                    }
-                    PipewireMessage::Disconnected => {
-                        self.clear();
+                    },
+                ),
+                PipewireMessage::LinkStateChanged { id, state, error } => {
+                    self.link_state_changed(id, state, error)
+                }
+                PipewireMessage::LinkFormatChanged { id, media_type } => {
+                    self.link_format_changed(id, media_type)
+                }
+                PipewireMessage::NodeRemoved { id } => self.remove_node(id),
+                PipewireMessage::PortRemoved { id, node_id } => self.remove_port(id, node_id),
+                PipewireMessage::LinkRemoved { id } => self.remove_link(id),
+                PipewireMessage::Connecting {
+                    remote,
+                    attempt,
+                    error,
+                    retry_in,
+                } => self.connection_failed(remote.as_deref(), attempt, &error, retry_in),
+                PipewireMessage::Connected => {
+                    self.obj().connection_banner().set_revealed(false);
+                }
+                PipewireMessage::Disconnected => {
+                    self.clear();
+                }
+                PipewireMessage::MalformedObject { id, reason } => {
+                    log::warn!("Server sent malformed object (id: {id}), ignoring it: {reason}");
+                    self.show_malformed_object(id, &reason);
+                }
+                PipewireMessage::Snapshot(messages) => {
+                    log::info!("Building graph from snapshot of {} objects", messages.len());
+
+                    // All widgets are created before anything is laid out, as layout only happens
+                    // once control returns to the main loop.
+                    for msg in messages {
+                        self.apply_message(msg);
                     }
-                };
+                }
+            }
+        }
+
+        /// Record the message to the trace, if recording.
+        fn record(&self, msg: &PipewireMessage) {
+            let mut recorder = self.recorder.borrow_mut();
+            let Some(writer) = recorder.as_mut() else {
+                return;
+            };
+
+            if let Err(e) = writer.record(msg) {
+                log::error!("Failed to record message, stopping recording: {e}");
+                recorder.take();
+            }
+        }
+
+        /// Show the details of a failed connection attempt in the banner.
+        fn connection_failed(
+            &self,
+            remote: Option<&str>,
+            attempt: u32,
+            error: &str,
+            retry_in: std::time::Duration,
+        ) {
+            let banner = self.obj().connection_banner();
+
+            let remote = remote.unwrap_or("the default remote");
+            let title = format!(
+                "Failed to connect to {remote} (attempt {attempt}): {error}. Retrying in {} s",
+                retry_in.as_secs_f32().ceil()
+            );
+            banner.set_title(&glib::markup_escape_text(&title));
+            banner.set_revealed(true);
+        }
+
+        /// Tell the user that an object was ignored because it is malformed.
+        ///
+        /// Further objects are counted in the same toast while it is shown, so that a misbehaving client
+        /// announcing many malformed objects doesn't flood the view with toasts.
+        fn show_malformed_object(&self, id: u32, reason: &str) {
+            let mut malformed_toast = self.malformed_toast.borrow_mut();
+            if let Some((toast, count)) = malformed_toast.as_mut() {
+                *count += 1;
+                toast.set_title(&format!("Ignored {count} malformed objects"));
+                return;
+            }
+
+            let toast = adw::Toast::new(&glib::markup_escape_text(&format!(
+                "Ignored malformed object {id}: {reason}"
+            )));
+            toast.connect_dismissed(glib::clone!(@weak self as imp => move |_| {
+                imp.malformed_toast.replace(None);
+            }));
+            self.obj().toast_overlay().add_toast(toast.clone());
+            *malformed_toast = Some((toast, 1));
+        }
+
+        /// Add a new node, showing it in the view unless it is hidden by the view filter.
+        fn add_node(&self, id: u32, info: NodeInfo) {
+            let visible = self.filter.borrow().is_node_visible(&info);
+            self.nodes.borrow_mut().insert(id, info);
+
+            if visible {
+                self.create_node_widget(id);
+            } else {
+                log::info!("Node (id: {id}) is hidden by the view filter");
             }
         }
 
-        /// Add a new node to the view.
-        fn add_node(&self, id: u32, name: &str, node_type: Option<NodeType>) {
+        /// Create the widget for a known node and add it to the view.
+        fn create_node_widget(&self, id: u32) {
             log::info!("Adding node to graph: id {}", id);
 
-            let node = graph::Node::new(name, id);
+            let nodes = self.nodes.borrow();
+            let Some(info) = nodes.get(&id) else {
+                log::warn!("Node (id: {id}) not found in graph manager");
+                return;
+            };
+
+            let node = graph::Node::new(&info.name, id);
+            node.set_media_name(info.media_name.as_str());
+            node.set_custom_label(self.node_label(&info.identity).unwrap_or_default());
+            node.set_port_sort_mode(self.port_sort_mode.get());
+            if let Some(target) = self.node_targets.borrow().get(&id) {
+                node.set_pinned_target(target.as_str());
+            }
+
+            // Remember the new label if the user renamed the node.
+            node.connect_local(
+                "label-edited",
+                false,
+                glib::clone!(@weak self as imp => @default-return None, move |args| {
+                    // Args always look like this: &[widget, label]
+                    let label = args[1].get::<String>().unwrap();
+
+                    imp.set_node_label(id, label);
+
+                    None
+                }),
+            );
 
             self.items.borrow_mut().insert(id, node.clone().upcast());
 
-            self.obj().graph().add_node(node, node_type);
+            self.obj().graph().add_node(node, info.node_type.clone());
         }
 
         /// Update a node tooltip to the view.
-        fn node_name_changed(&self, id: u32, node_name: &str, media_name: &str) {
-            let items = self.items.borrow();
+        fn node_name_changed(&self, id: u32, node_name: String, media_name: String) {
+            let mut nodes = self.nodes.borrow_mut();
+            let Some(info) = nodes.get_mut(&id) else {
+                log::warn!("Node (id: {id}) for changed name not found in graph manager");
+                return;
+            };
+            info.name = node_name;
+            info.media_name = media_name;
 
+            let items = self.items.borrow();
             let Some(node) = items.get(&id) else {
-                log::warn!("Node (id: {id}) for changed name not found in graph manager");
+                // The node is hidden, so there is no widget to update.
                 return;
             };
             let Some(node) = node.dynamic_cast_ref::<graph::Node>() else {
@@ -129,16 +599,127 @@ mod imp {
                 return;
             };
 
-            node.set_node_name(node_name);
-            node.set_media_name(media_name);
+            node.set_node_name(info.name.as_str());
+            node.set_media_name(info.media_name.as_str());
+        }
+
+        /// Show which node a stream node is pinned to by the session manager, if any.
+        fn node_target_changed(&self, id: u32, target: Option<String>) {
+            let items = self.items.borrow();
+            if let Some(node) = items
+                .get(&id)
+                .and_then(|item| item.downcast_ref::<graph::Node>())
+            {
+                node.set_pinned_target(target.as_deref().unwrap_or_default());
+            }
+
+            // Hidden nodes, and nodes that are not known yet, pick the target up once their widget is created.
+            let mut node_targets = self.node_targets.borrow_mut();
+            match target {
+                Some(target) => node_targets.insert(id, target),
+                None => node_targets.remove(&id),
+            };
         }
 
-        /// Remove the node with the specified id from the view.
+        /// Get the custom labels of nodes, mapping node identities to labels.
+        fn node_labels(&self) -> HashMap<String, String> {
+            self.obj()
+                .settings()
+                .value("node-labels")
+                .get()
+                .unwrap_or_default()
+        }
+
+        /// Get the custom label of nodes with the specified identity, if the user set one.
+        fn node_label(&self, identity: &str) -> Option<String> {
+            if identity.is_empty() {
+                return None;
+            }
+
+            self.node_labels().remove(identity)
+        }
+
+        /// Store a new custom label for a node, or remove it if the label is empty.
+        ///
+        /// The label is stored under the identity of the node, so that it applies to the node again once it is recreated.
+        fn set_node_label(&self, id: u32, label: String) {
+            let nodes = self.nodes.borrow();
+            let Some(info) = nodes.get(&id) else {
+                log::warn!("Node (id: {id}) for changed label not found in graph manager");
+                return;
+            };
+            if info.identity.is_empty() {
+                log::warn!("Node (id: {id}) has no identity to store its label under");
+                return;
+            }
+
+            let settings = self.obj().settings();
+            let mut labels = self.node_labels();
+            if label.is_empty() {
+                labels.remove(&info.identity);
+            } else {
+                labels.insert(info.identity.clone(), label.clone());
+            }
+            if let Err(e) = settings.set_value("node-labels", &labels.to_variant()) {
+                log::warn!("Failed to store node labels: {e}");
+            }
+
+            // A description that was written back can not be undone, so resetting only affects Helvum.
+            if settings.boolean("write-node-labels") && !label.is_empty() {
+                let backend = self.backend.get().expect("backend should be set");
+                backend.send(GtkMessage::SetNodeDescription {
+                    id,
+                    description: label,
+                });
+            }
+        }
+
+        /// Show the current custom labels on all node widgets, e.g. after a node was renamed.
+        fn update_node_labels(&self) {
+            let labels = self.node_labels();
+            let nodes = self.nodes.borrow();
+            let items = self.items.borrow();
+
+            for (id, info) in nodes.iter() {
+                let Some(node) = items
+                    .get(id)
+                    .and_then(|item| item.downcast_ref::<graph::Node>())
+                else {
+                    continue;
+                };
+
+                let label = labels.get(&info.identity).cloned().unwrap_or_default();
+                node.set_custom_label(label);
+            }
+        }
+
+        /// Remove the node with the specified id.
         fn remove_node(&self, id: u32) {
-            log::info!("Removing node from graph: id {}", id);
+            self.node_targets.borrow_mut().remove(&id);
+            if self.nodes.borrow_mut().remove(&id).is_none() {
+                log::warn!("Unknown node (id={id}) removed from graph");
+                return;
+            }
+
+            self.remove_node_widget(id);
+        }
+
+        /// Remove the widget of the node with the specified id from the view,
+        /// together with the widgets of its ports and their links.
+        fn remove_node_widget(&self, id: u32) {
+            let port_ids: Vec<u32> = self
+                .ports
+                .borrow()
+                .iter()
+                .filter(|(_, port)| port.node_id == id)
+                .map(|(port_id, _)| *port_id)
+                .collect();
+            for port_id in port_ids {
+                self.remove_port_widget(port_id, id);
+            }
 
             let Some(node) = self.items.borrow_mut().remove(&id) else {
-                log::warn!("Unknown node (id={id}) removed from graph");
+                // The node is hidden, so there is no widget to remove.
                 return;
             };
             let Ok(node) = node.dynamic_cast::<graph::Node>() else {
@@ -146,23 +727,41 @@ mod imp {
                 return;
             };
 
+            log::info!("Removing node from graph: id {}", id);
+
             self.obj().graph().remove_node(&node);
         }
 
-        /// Add a new port to the view.
-        fn add_port(
-            &self,
-            id: u32,
-            name: &str,
-            node_id: u32,
-            direction: pipewire::spa::utils::Direction,
-        ) {
-            log::info!("Adding port to graph: id {}", id);
+        /// Add a new port, showing it in the view unless it or its node are hidden by the view filter.
+        fn add_port(&self, id: u32, info: PortInfo) {
+            let node_id = info.node_id;
+            if !self.nodes.borrow().contains_key(&node_id) {
+                log::warn!("Node (id: {node_id}) for port (id: {id}) not found in graph manager");
+                return;
+            }
+
+            let visible = self.filter.borrow().is_port_visible(&info);
+            self.ports.borrow_mut().insert(id, info);
+
+            if visible {
+                self.create_port_widget(id);
+            }
+        }
+
+        /// Create the widget for a known port and add it to the widget of its node,
+        /// if the node is shown in the view.
+        fn create_port_widget(&self, id: u32) {
+            let ports = self.ports.borrow();
+            let Some(info) = ports.get(&id) else {
+                log::warn!("Port (id: {id}) not found in graph manager");
+                return;
+            };
+            let node_id = info.node_id;
 
             let mut items = self.items.borrow_mut();
 
             let Some(node) = items.get(&node_id) else {
-                log::warn!("Node (id: {node_id}) for port (id: {id}) not found in graph manager");
+                // The node is hidden, so the port is too.
                 return;
             };
             let Ok(node) = node.clone().dynamic_cast::<graph::Node>() else {
@@ -170,18 +769,35 @@ mod imp {
                 return;
             };
 
-            let port = graph::Port::new(id, name, direction);
+            log::info!("Adding port to graph: id {}", id);
+
+            let port = graph::Port::new(id, &info.name, info.direction);
+            port.set_media_type(info.media_type.as_raw());
+            port.set_is_monitor(info.is_monitor);
+            port.set_alias(info.alias.clone().unwrap_or_default());
+            port.set_port_index(info.port_index);
+            port.set_audio_channel(info.audio_channel.clone().unwrap_or_default());
+            port.set_object_path(info.object_path.clone().unwrap_or_default());
+            port.set_is_physical(info.is_physical);
+            port.set_is_terminal(info.is_terminal);
+            port.set_format(info.format.clone().unwrap_or_default());
+            port.set_label_mode(self.port_label_mode.get());
 
             // Create or delete a link if the widget emits the "port-toggled" signal.
             port.connect_local(
                 "port_toggled",
                 false,
                 glib::clone!(@weak self as app => @default-return None, move |args| {
-                    // Args always look like this: &[widget, id_port_from, id_port_to]
+                    // Args always look like this: &[widget, id_port_from, id_port_to, linger, passive]
                     let port_from = args[1].get::<u32>().unwrap();
                     let port_to = args[2].get::<u32>().unwrap();
+                    let options = LinkOptions {
+                        linger: args[3].get::<bool>().unwrap(),
+                        passive: args[4].get::<bool>().unwrap(),
+                        pin_target: app.obj().settings().boolean("pin-stream-targets"),
+                    };
 
-                    app.toggle_link(port_from, port_to);
+                    app.toggle_link(port_from, port_to, options);
 
                     None
                 }),
@@ -189,106 +805,223 @@ mod imp {
 
             items.insert(id, port.clone().upcast());
 
-            node.add_port(port);
+            // The port is added to its node together with other new ports, see `add_pending_ports`.
+            self.pending_ports.borrow_mut().push((node, port));
         }
 
-        fn port_media_type_changed(&self, id: u32, media_type: MediaType) {
-            let items = self.items.borrow();
-
-            let Some(port) = items.get(&id) else {
+        /// Update the media type of a port, and the details of its format if it was negotiated.
+        fn port_format_changed(&self, id: u32, media_type: MediaType, format: Option<String>) {
+            let mut ports = self.ports.borrow_mut();
+            let Some(info) = ports.get_mut(&id) else {
                 log::warn!("Port (id: {id}) for changed media type not found in graph manager");
                 return;
             };
-            let Some(port) = port.dynamic_cast_ref::<graph::Port>() else {
-                log::warn!("Graph Manager item under port id {id} is not a port");
-                return;
-            };
+            info.media_type = media_type;
+            info.format = format.clone();
+            let node_id = info.node_id;
+            let visible = self.filter.borrow().is_port_visible(info);
+            drop(ports);
+
+            let port = self.items.borrow().get(&id).cloned();
+            match (port, visible) {
+                (Some(port), true) => {
+                    let Some(port) = port.dynamic_cast_ref::<graph::Port>() else {
+                        log::warn!("Graph Manager item under port id {id} is not a port");
+                        return;
+                    };
 
-            port.set_media_type(media_type.as_raw())
+                    port.set_media_type(media_type.as_raw());
+                    port.set_format(format.unwrap_or_default());
+                }
+                (Some(_), false) => self.remove_port_widget(id, node_id),
+                (None, true) => {
+                    self.create_port_widget(id);
+                    self.create_link_widgets_of_port(id);
+                }
+                (None, false) => {}
+            }
         }
 
-        /// Remove the port with the id `id` from the node with the id `node_id`
-        /// from the view.
+        /// Remove the port with the id `id` from the node with the id `node_id`.
         fn remove_port(&self, id: u32, node_id: u32) {
-            log::info!("Removing port from graph: id {}, node_id: {}", id, node_id);
+            if self.ports.borrow_mut().remove(&id).is_none() {
+                log::warn!("Unknown Port (id: {id}) removed from graph");
+                return;
+            }
+
+            self.remove_port_widget(id, node_id);
+        }
+
+        /// Remove the widget of the port with the id `id` from the widget of the node with the id `node_id`,
+        /// together with the widgets of the ports links.
+        fn remove_port_widget(&self, id: u32, node_id: u32) {
+            let link_ids: Vec<u32> = self
+                .links
+                .borrow()
+                .iter()
+                .filter(|(_, link)| link.port_from == id || link.port_to == id)
+                .map(|(link_id, _)| *link_id)
+                .collect();
+            for link_id in link_ids {
+                self.remove_link_widget(link_id);
+            }
 
             let mut items = self.items.borrow_mut();
 
-            let Some(node) = items.get(&node_id) else {
-                log::warn!("Node (id: {node_id}) for port (id: {id}) not found in graph manager");
+            let Some(port) = items.remove(&id) else {
+                // The port is hidden, so there is no widget to remove.
                 return;
             };
-            let Ok(node) = node.clone().dynamic_cast::<graph::Node>() else {
-                log::warn!("Graph Manager item under node id {node_id} is not a node");
+            let Ok(port) = port.dynamic_cast::<graph::Port>() else {
+                log::warn!("Graph Manager item under port id {id} is not a port");
                 return;
             };
-            let Some(port) = items.remove(&id) else {
-                log::warn!("Unknown Port (id: {id}) removed from graph");
+
+            // A port that was not added to its node yet only needs to be forgotten.
+            {
+                let mut pending_ports = self.pending_ports.borrow_mut();
+                let pending_count = pending_ports.len();
+                pending_ports.retain(|(_, pending)| *pending != port);
+                if pending_ports.len() != pending_count {
+                    return;
+                }
+            }
+            let Some(node) = items.get(&node_id) else {
+                log::warn!("Node (id: {node_id}) for port (id: {id}) not found in graph manager");
                 return;
             };
-            let Ok(port) = port.dynamic_cast::<graph::Port>() else {
-                log::warn!("Graph Manager item under port id {id} is not a port");
+            let Some(node) = node.dynamic_cast_ref::<graph::Node>() else {
+                log::warn!("Graph Manager item under node id {node_id} is not a node");
                 return;
             };
 
+            log::info!("Removing port from graph: id {}, node_id: {}", id, node_id);
+
             node.remove_port(&port);
         }
 
-        /// Add a new link to the view.
-        fn add_link(
-            &self,
-            id: u32,
-            output_port_id: u32,
-            input_port_id: u32,
-            active: bool,
-            media_type: MediaType,
-        ) {
-            log::info!("Adding link to graph: id {}", id);
+        /// Add the port widgets that were created since the last call to their nodes.
+        ///
+        /// Ports of the same node are added together, so that the node only lays out its ports once.
+        fn add_pending_ports(&self) {
+            let pending_ports = self.pending_ports.take();
+            if pending_ports.is_empty() {
+                return;
+            }
+
+            let mut ports_by_node: HashMap<graph::Node, Vec<graph::Port>> = HashMap::new();
+            for (node, port) in pending_ports {
+                ports_by_node.entry(node).or_default().push(port);
+            }
+
+            for (node, ports) in ports_by_node {
+                node.add_ports(ports);
+            }
+        }
+
+        /// Add a new link, showing it in the view if both of its ports are shown.
+        fn add_link(&self, id: u32, info: LinkInfo) {
+            {
+                let ports = self.ports.borrow();
+                for port_id in [info.port_from, info.port_to] {
+                    if !ports.contains_key(&port_id) {
+                        log::warn!(
+                            "Port (id: {port_id}) for link (id: {id}) not found in graph manager"
+                        );
+                        return;
+                    }
+                }
+            }
+
+            self.links.borrow_mut().insert(id, info);
+
+            self.create_link_widget(id);
+        }
+
+        /// Create the widget for a known link and add it to the view,
+        /// if both of its ports are shown in the view.
+        fn create_link_widget(&self, id: u32) {
+            // The ports of the link need to be on their nodes for the link to be drawn.
+            self.add_pending_ports();
+
+            let links = self.links.borrow();
+            let Some(info) = links.get(&id) else {
+                log::warn!("Link (id: {id}) not found in graph manager");
+                return;
+            };
+            let output_port_id = info.port_from;
+            let input_port_id = info.port_to;
 
             let mut items = self.items.borrow_mut();
 
-            let Some(output_port) = items.get(&output_port_id) else {
-                log::warn!("Output port (id: {output_port_id}) for link (id: {id}) not found in graph manager");
+            let (Some(output_port), Some(input_port)) =
+                (items.get(&output_port_id), items.get(&input_port_id))
+            else {
+                // At least one of the ports is hidden, so the link is too.
                 return;
             };
             let Ok(output_port) = output_port.clone().dynamic_cast::<graph::Port>() else {
                 log::warn!("Graph Manager item under port id {output_port_id} is not a port");
                 return;
             };
-            let Some(input_port) = items.get(&input_port_id) else {
-                log::warn!("Output port (id: {input_port_id}) for link (id: {id}) not found in graph manager");
-                return;
-            };
             let Ok(input_port) = input_port.clone().dynamic_cast::<graph::Port>() else {
                 log::warn!("Graph Manager item under port id {input_port_id} is not a port");
                 return;
             };
 
+            log::info!("Adding link to graph: id {}", id);
+
             let link = graph::Link::new();
             link.set_output_port(Some(&output_port));
             link.set_input_port(Some(&input_port));
-            link.set_active(active);
-            link.set_media_type(media_type);
+            link.set_state(info.state);
+            link.set_error(info.error.as_deref());
+            link.set_passive(info.passive);
+            link.set_linger(info.linger);
+            link.set_created_by_helvum(info.created_by_helvum);
+            link.set_media_type(info.media_type);
 
             items.insert(id, link.clone().upcast());
 
             // Update graph to contain the new link.
-            self.graph
-                .get()
-                .expect("graph should be set")
-                .add_link(link);
+            self.obj().graph().add_link(link);
         }
 
-        fn link_state_changed(&self, id: u32, active: bool) {
-            log::info!(
-                "Link state changed: Link (id={id}) is now {}",
-                if active { "active" } else { "inactive" }
-            );
+        /// Create the widgets of all links of the port with the specified id that are not shown yet.
+        fn create_link_widgets_of_port(&self, id: u32) {
+            let link_ids: Vec<u32> = self
+                .links
+                .borrow()
+                .iter()
+                .filter(|(_, link)| link.port_from == id || link.port_to == id)
+                .map(|(link_id, _)| *link_id)
+                .collect();
+
+            for link_id in link_ids {
+                if !self.items.borrow().contains_key(&link_id) {
+                    self.create_link_widget(link_id);
+                }
+            }
+        }
+
+        fn link_state_changed(&self, id: u32, state: LinkState, error: Option<String>) {
+            match error {
+                Some(ref error) => log::info!("Link state changed: Link (id={id}) failed: {error}"),
+                None => log::info!("Link state changed: Link (id={id}) is now {state:?}"),
+            }
+
+            let mut links = self.links.borrow_mut();
+            let Some(info) = links.get_mut(&id) else {
+                log::warn!("Link state changed on unknown link (id={id})");
+                return;
+            };
+            info.state = state;
+            info.error = error.clone();
 
             let items = self.items.borrow();
 
             let Some(link) = items.get(&id) else {
-                log::warn!("Link state changed on unknown link (id={id})");
+                // The link is hidden, so there is no widget to update.
                 return;
             };
             let Some(link) = link.dynamic_cast_ref::<graph::Link>() else {
@@ -296,7 +1029,8 @@ mod imp {
                 return;
             };
 
-            link.set_active(active);
+            link.set_state(state);
+            link.set_error(error.as_deref());
         }
 
         fn link_format_changed(
@@ -304,10 +1038,17 @@ mod imp {
             id: u32,
             media_type: pipewire::spa::param::format::MediaType,
         ) {
+            let mut links = self.links.borrow_mut();
+            let Some(info) = links.get_mut(&id) else {
+                log::warn!("Link (id: {id}) for changed media type not found in graph manager");
+                return;
+            };
+            info.media_type = media_type;
+
             let items = self.items.borrow();
 
             let Some(link) = items.get(&id) else {
-                log::warn!("Link (id: {id}) for changed media type not found in graph manager");
+                // The link is hidden, so there is no widget to update.
                 return;
             };
             let Some(link) = link.dynamic_cast_ref::<graph::Link>() else {
@@ -318,19 +1059,29 @@ mod imp {
         }
 
         // Toggle a link between the two specified ports on the remote pipewire server.
-        fn toggle_link(&self, port_from: u32, port_to: u32) {
-            let sender = self.pw_sender.get().expect("pw_sender shoud be set");
-            sender
-                .send(crate::GtkMessage::ToggleLink { port_from, port_to })
-                .expect("Failed to send message");
+        fn toggle_link(&self, port_from: u32, port_to: u32, options: LinkOptions) {
+            let backend = self.backend.get().expect("backend should be set");
+            backend.send(GtkMessage::ToggleLink {
+                port_from,
+                port_to,
+                options,
+            });
         }
 
-        /// Remove the link with the specified id from the view.
+        /// Remove the link with the specified id.
         fn remove_link(&self, id: u32) {
-            log::info!("Removing link from graph: id {}", id);
+            if self.links.borrow_mut().remove(&id).is_none() {
+                log::warn!("Unknown Link (id={id}) removed from graph");
+                return;
+            }
+
+            self.remove_link_widget(id);
+        }
 
+        /// Remove the widget of the link with the specified id from the view.
+        fn remove_link_widget(&self, id: u32) {
             let Some(link) = self.items.borrow_mut().remove(&id) else {
-                log::warn!("Unknown Link (id={id}) removed from graph");
+                // The link is hidden, so there is no widget to remove.
                 return;
             };
             let Ok(link) = link.dynamic_cast::<graph::Link>() else {
@@ -338,11 +1089,128 @@ mod imp {
                 return;
             };
 
+            log::info!("Removing link from graph: id {}", id);
+
             self.obj().graph().remove_link(&link);
         }
 
+        /// Sort the ports of all shown nodes according to the current sort mode.
+        fn update_port_order(&self) {
+            let sort_mode = self.port_sort_mode.get();
+
+            for item in self.items.borrow().values() {
+                if let Some(node) = item.downcast_ref::<graph::Node>() {
+                    node.set_port_sort_mode(sort_mode);
+                }
+            }
+        }
+
+        /// Show the name selected by the current label mode on all shown ports.
+        fn update_port_labels(&self) {
+            let label_mode = self.port_label_mode.get();
+            let items = self.items.borrow();
+
+            for item in items.values() {
+                if let Some(port) = item.downcast_ref::<graph::Port>() {
+                    port.set_label_mode(label_mode);
+                }
+            }
+
+            // Ports may be sorted by their shown name.
+            for item in items.values() {
+                if let Some(node) = item.downcast_ref::<graph::Node>() {
+                    node.sort_ports();
+                }
+            }
+        }
+
+        /// Apply the current view filter to all known objects,
+        /// removing widgets of objects that are now hidden and creating widgets for objects that are now shown.
+        fn refilter(&self) {
+            let filter = self.filter.borrow();
+
+            let hidden_ports: Vec<(u32, u32)> = {
+                let items = self.items.borrow();
+                self.ports
+                    .borrow()
+                    .iter()
+                    .filter(|(id, port)| items.contains_key(*id) && !filter.is_port_visible(port))
+                    .map(|(id, port)| (*id, port.node_id))
+                    .collect()
+            };
+            for (id, node_id) in hidden_ports {
+                self.remove_port_widget(id, node_id);
+            }
+
+            let hidden_nodes: Vec<u32> = {
+                let items = self.items.borrow();
+                self.nodes
+                    .borrow()
+                    .iter()
+                    .filter(|(id, node)| items.contains_key(*id) && !filter.is_node_visible(node))
+                    .map(|(id, _)| *id)
+                    .collect()
+            };
+            for id in hidden_nodes {
+                self.remove_node_widget(id);
+            }
+
+            let shown_nodes: Vec<u32> = {
+                let items = self.items.borrow();
+                self.nodes
+                    .borrow()
+                    .iter()
+                    .filter(|(id, node)| !items.contains_key(*id) && filter.is_node_visible(node))
+                    .map(|(id, _)| *id)
+                    .collect()
+            };
+            for id in shown_nodes {
+                self.create_node_widget(id);
+            }
+
+            let shown_ports: Vec<u32> = {
+                let items = self.items.borrow();
+                self.ports
+                    .borrow()
+                    .iter()
+                    .filter(|(id, port)| {
+                        !items.contains_key(*id)
+                            && items.contains_key(&port.node_id)
+                            && filter.is_port_visible(port)
+                    })
+                    .map(|(id, _)| *id)
+                    .collect()
+            };
+            for id in shown_ports {
+                self.create_port_widget(id);
+            }
+            self.add_pending_ports();
+
+            let shown_links: Vec<u32> = {
+                let items = self.items.borrow();
+                self.links
+                    .borrow()
+                    .iter()
+                    .filter(|(id, link)| {
+                        !items.contains_key(*id)
+                            && items.contains_key(&link.port_from)
+                            && items.contains_key(&link.port_to)
+                    })
+                    .map(|(id, _)| *id)
+                    .collect()
+            };
+            for id in shown_links {
+                self.create_link_widget(id);
+            }
+        }
+
         fn clear(&self) {
+            self.pending_ports.borrow_mut().clear();
             self.items.borrow_mut().clear();
+            self.nodes.borrow_mut().clear();
+            self.ports.borrow_mut().clear();
+            self.links.borrow_mut().clear();
+            self.node_targets.borrow_mut().clear();
             self.obj().graph().clear();
         }
     }
@@ -360,20 +1228,320 @@ impl GraphManager {
     pub fn new(
         graph: &GraphView,
         connection_banner: &adw::Banner,
-        sender: PwSender<GtkMessage>,
+        toast_overlay: &adw::ToastOverlay,
+        settings: &gio::Settings,
+        backend: Rc<dyn Backend>,
         receiver: async_channel::Receiver<PipewireMessage>,
     ) -> Self {
         let res: Self = glib::Object::builder()
             .property("graph", graph)
             .property("connection-banner", connection_banner)
+            .property("toast-overlay", toast_overlay)
+            .property("settings", settings)
             .build();
 
         glib::MainContext::default().spawn_local(receive(res.clone(), receiver));
         assert!(
-            res.imp().pw_sender.set(sender).is_ok(),
-            "Should be able to set pw_sender)"
+            res.imp().backend.set(backend).is_ok(),
+            "Should be able to set backend"
         );
 
         res
     }
+
+    /// Record all messages received from now on to the trace.
+    pub fn record_to(&self, writer: TraceWriter) {
+        self.imp().recorder.replace(Some(writer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use adw::gtk;
+
+    use super::*;
+    use crate::{backend::ScriptedBackend, ui::graph};
+
+    /// Provides the graph from a script, and records the messages sent by the graph manager.
+    struct RecordingBackend {
+        scripted: ScriptedBackend,
+        sent: RefCell<Vec<GtkMessage>>,
+    }
+
+    impl Backend for RecordingBackend {
+        fn send(&self, msg: GtkMessage) {
+            self.sent.borrow_mut().push(msg.clone());
+            self.scripted.send(msg);
+        }
+
+        fn terminate(&self) {
+            self.scripted.terminate();
+        }
+    }
+
+    /// A graph manager showing the graph of a scripted backend, without a pipewire server.
+    struct Harness {
+        graph_manager: GraphManager,
+        graph: GraphView,
+        backend: Rc<RecordingBackend>,
+    }
+
+    impl Harness {
+        fn new(script: Vec<PipewireMessage>) -> Self {
+            adw::init().expect("Failed to initialize libadwaita");
+
+            let (scripted, receiver) = ScriptedBackend::new(script);
+            let backend = Rc::new(RecordingBackend {
+                scripted,
+                sent: RefCell::default(),
+            });
+            let graph = GraphView::new();
+            let graph_manager = GraphManager::new(
+                &graph,
+                &adw::Banner::new(""),
+                &adw::ToastOverlay::new(),
+                &settings(),
+                backend.clone(),
+                receiver,
+            );
+
+            let harness = Self {
+                graph_manager,
+                graph,
+                backend,
+            };
+            harness.process();
+            harness
+        }
+
+        /// Send another message from the backend and let the graph manager handle it.
+        fn push(&self, msg: PipewireMessage) {
+            self.backend.scripted.push(msg);
+            self.process();
+        }
+
+        /// Let the graph manager handle all messages that were sent so far.
+        fn process(&self) {
+            let context = glib::MainContext::default();
+            while context.iteration(false) {}
+        }
+
+        /// Get the widget shown for the object with the specified id.
+        fn item<T: IsA<glib::Object>>(&self, id: u32) -> Option<T> {
+            self.graph_manager
+                .imp()
+                .items
+                .borrow()
+                .get(&id)
+                .and_then(|item| item.clone().downcast().ok())
+        }
+    }
+
+    /// Settings that are kept in memory, using the schema compiled by the build script.
+    fn settings() -> gio::Settings {
+        let dir = option_env!("HELVUM_SCHEMA_DIR")
+            .expect("Settings schema should have been compiled by the build script");
+        let schema = gio::SettingsSchemaSource::from_directory(dir, None, false)
+            .expect("Failed to load settings schema")
+            .lookup("org.pipewire.Helvum", false)
+            .expect("Settings schema should contain the Helvum schema");
+
+        gio::Settings::new_full(&schema, Some(&gio::memory_settings_backend_new()), None)
+    }
+
+    fn node_added(id: u32, node_type: NodeType) -> PipewireMessage {
+        PipewireMessage::NodeAdded {
+            id,
+            identity: format!("test.node.{id}"),
+            name: format!("Node {id}"),
+            node_type: Some(node_type),
+            media_class: None,
+            is_virtual: false,
+        }
+    }
+
+    fn port_added(id: u32, node_id: u32, direction: Direction) -> PipewireMessage {
+        PipewireMessage::PortAdded {
+            id,
+            node_id,
+            name: format!("port_{id}"),
+            direction,
+            is_monitor: false,
+            is_midi: false,
+            alias: None,
+            port_index: None,
+            audio_channel: None,
+            object_path: None,
+            is_physical: false,
+            is_terminal: false,
+        }
+    }
+
+    fn link_added(id: u32, port_from: u32, port_to: u32) -> PipewireMessage {
+        PipewireMessage::LinkAdded {
+            id,
+            port_from,
+            port_to,
+            state: LinkState::Active,
+            error: None,
+            passive: false,
+            linger: true,
+            created_by_helvum: false,
+            media_type: MediaType::Audio,
+        }
+    }
+
+    /// Two nodes with a port each, and a link between the ports.
+    fn script() -> Vec<PipewireMessage> {
+        vec![
+            node_added(1, NodeType::Output),
+            node_added(2, NodeType::Input),
+            port_added(3, 1, Direction::Output),
+            port_added(4, 2, Direction::Input),
+            link_added(5, 3, 4),
+        ]
+    }
+
+    /// Check that the nodes, ports and link of [`script`] are shown.
+    fn assert_script_shown(harness: &Harness) {
+        for node_id in [1, 2] {
+            let node: graph::Node = harness.item(node_id).expect("Node should be shown");
+            assert_eq!(node.parent().as_ref(), Some(harness.graph.upcast_ref()));
+        }
+        for (port_id, node_id) in [(3, 1), (4, 2)] {
+            let port: graph::Port = harness.item(port_id).expect("Port should be shown");
+            let node: graph::Node = harness.item(node_id).unwrap();
+            assert_eq!(
+                port.ancestor(graph::Node::static_type()).as_ref(),
+                Some(node.upcast_ref())
+            );
+        }
+        let link: graph::Link = harness.item(5).expect("Link should be shown");
+        assert_eq!(link.output_port().map(|port| port.pipewire_id()), Some(3));
+        assert_eq!(link.input_port().map(|port| port.pipewire_id()), Some(4));
+    }
+
+    #[test]
+    fn objects_are_added() {
+        gtk::test_synced(|| {
+            let harness = Harness::new(script());
+            assert_script_shown(&harness);
+        });
+    }
+
+    #[test]
+    fn snapshot_is_applied_at_once() {
+        gtk::test_synced(|| {
+            let harness = Harness::new(vec![PipewireMessage::Snapshot(script())]);
+            assert_script_shown(&harness);
+        });
+    }
+
+    #[test]
+    fn objects_are_removed() {
+        gtk::test_synced(|| {
+            let harness = Harness::new(script());
+
+            harness.push(PipewireMessage::LinkRemoved { id: 5 });
+            assert!(harness.item::<graph::Link>(5).is_none());
+            assert!(harness.item::<graph::Port>(3).is_some());
+
+            harness.push(PipewireMessage::PortRemoved { id: 4, node_id: 2 });
+            assert!(harness.item::<graph::Port>(4).is_none());
+            assert!(harness.item::<graph::Node>(2).is_some());
+
+            harness.push(PipewireMessage::NodeRemoved { id: 1 });
+            assert!(harness.item::<graph::Node>(1).is_none());
+            assert!(harness.item::<graph::Port>(3).is_none());
+            assert!(harness.item::<graph::Node>(2).is_some());
+        });
+    }
+
+    #[test]
+    fn removing_node_removes_its_ports_and_links() {
+        gtk::test_synced(|| {
+            let harness = Harness::new(script());
+
+            harness.push(PipewireMessage::NodeRemoved { id: 2 });
+            assert!(harness.item::<graph::Node>(2).is_none());
+            assert!(harness.item::<graph::Port>(4).is_none());
+            assert!(harness.item::<graph::Link>(5).is_none());
+            assert!(harness.item::<graph::Port>(3).is_some());
+        });
+    }
+
+    #[test]
+    fn disconnecting_clears_graph() {
+        gtk::test_synced(|| {
+            let harness = Harness::new(script());
+            let node: graph::Node = harness.item(1).unwrap();
+
+            harness.push(PipewireMessage::Disconnected);
+            assert!(harness.graph_manager.imp().items.borrow().is_empty());
+            assert!(node.parent().is_none());
+        });
+    }
+
+    #[test]
+    fn toggling_ports_sends_toggle_link() {
+        gtk::test_synced(|| {
+            let harness = Harness::new(script());
+            let port: graph::Port = harness.item(3).unwrap();
+
+            // Args are: id_port_from, id_port_to, linger, passive
+            port.emit_by_name::<()>("port-toggled", &[&3u32, &4u32, &false, &true]);
+
+            let sent = harness.backend.sent.borrow();
+            assert!(
+                matches!(
+                    sent.as_slice(),
+                    [GtkMessage::ToggleLink {
+                        port_from: 3,
+                        port_to: 4,
+                        options: LinkOptions {
+                            linger: false,
+                            passive: true,
+                            pin_target: false,
+                        },
+                    }]
+                ),
+                "Unexpected messages sent: {sent:?}"
+            );
+        });
+    }
+
+    #[test]
+    fn removing_traced_node_retraces_signal_path() {
+        gtk::test_synced(|| {
+            let mut script = script();
+            // Continue the signal path from the second node to a third one.
+            script.extend([
+                node_added(6, NodeType::Input),
+                port_added(7, 2, Direction::Output),
+                port_added(8, 6, Direction::Input),
+                link_added(9, 7, 8),
+            ]);
+            let harness = Harness::new(script);
+            let start: graph::Node = harness.item(1).unwrap();
+            let end: graph::Node = harness.item(6).unwrap();
+
+            harness.graph.trace_signal_path(&start, Direction::Output);
+            assert_eq!(harness.graph.signal_trace_endpoints(), Some(vec![end]));
+
+            harness.push(PipewireMessage::NodeRemoved { id: 6 });
+
+            let middle: graph::Node = harness.item(2).unwrap();
+            assert_eq!(harness.graph.signal_trace_endpoints(), Some(vec![middle]));
+            let port: graph::Port = harness.item(4).unwrap();
+            assert!(port.has_css_class("traced"));
+            let port: graph::Port = harness.item(7).unwrap();
+            assert!(!port.has_css_class("traced"));
+
+            // Removing the node the signal path was traced from ends the trace.
+            harness.push(PipewireMessage::NodeRemoved { id: 1 });
+            assert_eq!(harness.graph.signal_trace_endpoints(), None);
+        });
+    }
 }
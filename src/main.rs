@@ -15,6 +15,7 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 mod application;
+mod backend;
 mod graph_manager;
 mod pipewire_connection;
 mod ui;
@@ -25,10 +26,18 @@ use pipewire::spa::{param::format::MediaType, utils::Direction};
 /// Messages sent by the GTK thread to notify the pipewire thread.
 #[derive(Debug, Clone)]
 pub enum GtkMessage {
-    /// Toggle a link between the two specified ports.
-    ToggleLink { port_from: u32, port_to: u32 },
+    /// Toggle a link between the two specified ports, creating it with the specified options.
+    ToggleLink {
+        port_from: u32,
+        port_to: u32,
+        options: LinkOptions,
+    },
+    /// Write a new `node.description` to the specified node, if the node supports it.
+    SetNodeDescription { id: u32, description: String },
     /// Connect to PipeWire service.
     Connect(Option<String>),
+    /// Stop waiting for the next connection attempt and try to connect immediately.
+    Retry,
     /// Quit the event loop and let the thread finish.
     Terminate,
 }
@@ -38,34 +47,73 @@ pub enum GtkMessage {
 pub enum PipewireMessage {
     NodeAdded {
         id: u32,
+        /// A name of the node that stays the same when the node is recreated, used to remember user labels.
+        identity: String,
         name: String,
         node_type: Option<NodeType>,
+        media_class: Option<String>,
+        is_virtual: bool,
     },
     NodeNameChanged {
         id: u32,
         name: String,
         media_name: String,
     },
+    /// The session manager was told to keep a stream node routed to another node, through the `default` metadata.
+    NodeTargetChanged {
+        id: u32,
+        /// The name of the node that the stream is kept routed to, or `None` if it is routed freely.
+        target: Option<String>,
+    },
     PortAdded {
         id: u32,
         node_id: u32,
         name: String,
         direction: Direction,
+        is_monitor: bool,
+        /// Whether the port carries MIDI, from the `format.dsp` property.
+        is_midi: bool,
+        /// The `port.alias` property.
+        alias: Option<String>,
+        /// The index of the port on its node, from the `port.id` property.
+        port_index: Option<u32>,
+        /// The `audio.channel` property.
+        audio_channel: Option<String>,
+        /// The `object.path` property.
+        object_path: Option<String>,
+        /// Whether the port is a port of a physical device, from the `port.physical` property.
+        is_physical: bool,
+        /// Whether the port is the start or end of a signal chain, from the `port.terminal` property.
+        is_terminal: bool,
     },
     PortFormatChanged {
         id: u32,
         media_type: MediaType,
+        /// A description of the negotiated format of the port, e.g. "F32LE, 48000 Hz, 2 channels".
+        ///
+        /// This is only set if the format was negotiated, and not just enumerated as a possible format.
+        format: Option<String>,
     },
     LinkAdded {
         id: u32,
         port_from: u32,
         port_to: u32,
-        active: bool,
+        state: LinkState,
+        /// Why the link failed, if its state is [`LinkState::Error`].
+        error: Option<String>,
+        /// Whether the link does not keep its nodes running, from the `link.passive` property.
+        passive: bool,
+        /// Whether the link stays after the client that created it disconnected, from the `object.linger` property.
+        linger: bool,
+        /// Whether the link was created by Helvum.
+        created_by_helvum: bool,
         media_type: MediaType,
     },
     LinkStateChanged {
         id: u32,
-        active: bool,
+        state: LinkState,
+        /// Why the link failed, if its state is [`LinkState::Error`].
+        error: Option<String>,
     },
     LinkFormatChanged {
         id: u32,
@@ -81,9 +129,62 @@ pub enum PipewireMessage {
     LinkRemoved {
         id: u32,
     },
-    Connecting,
+    /// A connection attempt failed, the next one is made in `retry_in`.
+    Connecting {
+        /// The remote that is being connected to, `None` for the default remote.
+        remote: Option<String>,
+        /// The number of failed attempts since the last successful connection.
+        attempt: u32,
+        error: String,
+        retry_in: std::time::Duration,
+    },
     Connected,
     Disconnected,
+    /// All objects of the remote that existed when connecting, sent at once after connecting.
+    ///
+    /// Contains the messages that would otherwise have been sent one by one.
+    Snapshot(Vec<PipewireMessage>),
+    /// An object sent by the server was malformed and is ignored.
+    MalformedObject {
+        id: u32,
+        reason: String,
+    },
+}
+
+/// How a link that is created by Helvum behaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkOptions {
+    /// Whether the link stays after Helvum closes, set as the `object.linger` property.
+    pub linger: bool,
+    /// Whether the link does not keep its nodes running, set as the `link.passive` property.
+    pub passive: bool,
+    /// Whether to pin the stream on one end of the link to the node on the other end,
+    /// so that the session manager does not move it elsewhere.
+    pub pin_target: bool,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        Self {
+            linger: true,
+            passive: false,
+            pin_target: false,
+        }
+    }
+}
+
+/// The state of a link, see [`pipewire::link::LinkState`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "HelvumLinkState")]
+pub enum LinkState {
+    Error,
+    Unlinked,
+    #[default]
+    Init,
+    Negotiating,
+    Allocating,
+    Paused,
+    Active,
 }
 
 #[derive(Debug, Clone)]
@@ -120,22 +221,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let ctx = glib::MainContext::default();
     let _guard = ctx.acquire().unwrap();
 
-    // Start the pipewire thread with channels in both directions.
-
-    let (gtk_sender, gtk_receiver) = async_channel::unbounded();
-    let (pw_sender, pw_receiver) = pipewire::channel::channel();
-    let pw_thread =
-        std::thread::spawn(move || pipewire_connection::thread_main(gtk_sender, pw_receiver));
-
-    let app = application::Application::new(gtk_receiver, pw_sender.clone());
+    // Pipewire threads for each connected remote are started and terminated by the application.
+    let app = application::Application::new();
 
     app.run();
 
-    pw_sender
-        .send(GtkMessage::Terminate)
-        .expect("Failed to send message");
-
-    pw_thread.join().expect("Pipewire thread panicked");
-
     Ok(())
 }
@@ -16,7 +16,15 @@
 
 mod state;
 
-use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    os::unix::fs::FileTypeExt,
+    path::PathBuf,
+    rc::Rc,
+    thread::JoinHandle,
+    time::Duration,
+};
 
 use adw::glib::{self, clone};
 use log::{debug, error, info, warn};
@@ -26,38 +34,162 @@ use pipewire::{
     keys,
     link::{Link, LinkChangeMask, LinkInfoRef, LinkListener, LinkState},
     main_loop::MainLoop,
-    node::{Node, NodeInfoRef, NodeListener},
+    metadata::{Metadata, MetadataListener},
+    node::{Node, NodeChangeMask, NodeInfoRef, NodeListener},
     port::{Port, PortChangeMask, PortInfoRef, PortListener},
     properties::{properties, Properties},
     registry::{GlobalObject, Registry},
     spa::{
-        param::{ParamInfoFlags, ParamType},
+        param::{audio::AudioInfoRaw, format::MediaSubtype, ParamInfoFlags, ParamType},
+        pod::{serialize::PodSerializer, Object, Pod, Property, PropertyFlags, Value},
+        sys::SPA_PROP_params,
         utils::dict::DictRef,
         utils::result::SpaResult,
+        utils::{Direction, SpaTypes},
     },
     types::ObjectType,
 };
 
-use crate::{GtkMessage, MediaType, NodeType, PipewireMessage};
+use crate::{backend::Backend, GtkMessage, LinkOptions, MediaType, NodeType, PipewireMessage};
 use state::{Item, State};
 
+/// Property that marks links created by Helvum, so that they can be told apart from links of other applications.
+const CREATED_BY_HELVUM_KEY: &str = "helvum.link";
+
+/// Metadata key that the session manager reads the node a stream should be routed to from, by `object.serial`.
+const TARGET_OBJECT_KEY: &str = "target.object";
+/// Older metadata key that the session manager reads the node a stream should be routed to from, by id.
+const TARGET_NODE_KEY: &str = "target.node";
+
 enum ProxyItem {
     Node {
-        _proxy: Node,
+        proxy: Node,
         _listener: NodeListener,
     },
     Port {
         proxy: Port,
+        formats: Rc<RefCell<PortFormats>>,
         _listener: PortListener,
     },
     Link {
         _proxy: Link,
         _listener: LinkListener,
     },
+    Metadata {
+        proxy: Metadata,
+        _listener: MetadataListener,
+    },
+}
+
+/// Tracks the format params of a port, so that they are only enumerated when they changed.
+#[derive(Default)]
+struct PortFormats {
+    /// The flags of the `EnumFormat` param in the last info of the port.
+    enum_format_flags: Option<ParamInfoFlags>,
+    /// The flags of the `Format` param in the last info of the port.
+    format_flags: Option<ParamInfoFlags>,
+    /// Whether the negotiated format was received, which takes precedence over the enumerated formats.
+    negotiated: bool,
+    /// Params that changed while the port was held back, to be enumerated once it is announced.
+    pending: Vec<ParamType>,
+}
+
+impl PortFormats {
+    /// Remember the flags of the format params in a port info, returning the params that need to be enumerated.
+    fn update(
+        &mut self,
+        params: impl IntoIterator<Item = (ParamType, ParamInfoFlags)>,
+    ) -> Vec<ParamType> {
+        let mut enum_format_changed = false;
+        let mut format_changed = false;
+        for (param_id, flags) in params {
+            let (last_flags, changed) = match param_id {
+                ParamType::EnumFormat => (&mut self.enum_format_flags, &mut enum_format_changed),
+                ParamType::Format => (&mut self.format_flags, &mut format_changed),
+                _ => continue,
+            };
+            // PipeWire toggles the serial flag of a param whenever the param changes.
+            *changed = last_flags.replace(flags) != Some(flags);
+        }
+
+        let is_readable = |flags: Option<ParamInfoFlags>| {
+            flags.is_some_and(|flags| flags.contains(ParamInfoFlags::READ))
+        };
+
+        if format_changed {
+            self.negotiated = false;
+            // Without a negotiated format, the enumerated formats are all that is known about the port.
+            if !is_readable(self.format_flags) {
+                enum_format_changed = true;
+            }
+        }
+
+        let mut changed = Vec::new();
+        if enum_format_changed && is_readable(self.enum_format_flags) {
+            changed.push(ParamType::EnumFormat);
+        }
+        if format_changed && is_readable(self.format_flags) {
+            changed.push(ParamType::Format);
+        }
+        changed
+    }
+}
+
+/// Sends messages about the graph to the GTK thread.
+///
+/// Right after connecting, the remote announces all of its objects at once.
+/// Until that initial state was received, messages are collected and then sent
+/// as a single [`PipewireMessage::Snapshot`], so that the GTK thread can build the graph in one go.
+#[derive(Clone)]
+struct GtkSender {
+    sender: async_channel::Sender<PipewireMessage>,
+    /// The messages collected so far, or `None` once the snapshot was sent.
+    snapshot: Rc<RefCell<Option<Vec<PipewireMessage>>>>,
+}
+
+impl GtkSender {
+    fn new(sender: async_channel::Sender<PipewireMessage>) -> Self {
+        Self {
+            sender,
+            snapshot: Rc::new(RefCell::new(Some(Vec::new()))),
+        }
+    }
+
+    fn send(&self, msg: PipewireMessage) {
+        if let Some(snapshot) = self.snapshot.borrow_mut().as_mut() {
+            snapshot.push(msg);
+            return;
+        }
+
+        self.sender
+            .send_blocking(msg)
+            .expect("Failed to send message");
+    }
+
+    /// Get the number of messages collected so far, or `None` if the snapshot was already sent.
+    fn snapshot_len(&self) -> Option<usize> {
+        self.snapshot.borrow().as_ref().map(Vec::len)
+    }
+
+    /// Send the collected messages, sending all further messages right away.
+    fn send_snapshot(&self) {
+        let Some(snapshot) = self.snapshot.take() else {
+            return;
+        };
+
+        info!("Initial sync done, sending {} objects", snapshot.len());
+        self.sender
+            .send_blocking(PipewireMessage::Snapshot(snapshot))
+            .expect("Failed to send message");
+    }
 }
 
 struct LoopState {
     is_stopped: bool,
+    /// Whether a remote to connect to was selected yet.
+    has_remote: bool,
+    /// Number of failed attempts to connect to the selected remote since it was selected or last connected to.
+    attempt: u32,
     props: Properties,
 }
 
@@ -65,30 +197,136 @@ impl LoopState {
     fn handle_message(&mut self, msg: GtkMessage) -> bool {
         match msg {
             GtkMessage::Terminate => self.is_stopped = true,
-            GtkMessage::Connect(remote) => match remote {
-                Some(s) => self.props.insert(*keys::REMOTE_NAME, s),
-                None => self.props.remove(*keys::REMOTE_NAME),
-            },
+            GtkMessage::Connect(remote) => {
+                match remote {
+                    Some(s) => self.props.insert(*keys::REMOTE_NAME, s),
+                    None => self.props.remove(*keys::REMOTE_NAME),
+                }
+                self.has_remote = true;
+                // The backoff of the previous remote does not apply to the new one.
+                self.attempt = 0;
+            }
+            // Cut the wait for the next connection attempt short.
+            // Before a remote was selected, there is nothing to retry yet.
+            GtkMessage::Retry => return self.has_remote,
             _ => return false,
         }
         true
     }
 }
 
+/// Find the sockets of pipewire remotes that can be connected to.
+///
+/// Remotes are searched for in `$PIPEWIRE_RUNTIME_DIR`, or `$XDG_RUNTIME_DIR` if that is unset,
+/// and are returned by their socket name, which can be passed in [`GtkMessage::Connect`].
+pub(super) fn available_remotes() -> Vec<String> {
+    let runtime_dir = std::env::var_os("PIPEWIRE_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(glib::user_runtime_dir);
+
+    let entries = match std::fs::read_dir(&runtime_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to list remotes in {}: {e}", runtime_dir.display());
+            return Vec::new();
+        }
+    };
+
+    let mut remotes: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|ty| ty.is_socket()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with("pipewire-"))
+        .collect();
+    remotes.sort_unstable();
+
+    remotes
+}
+
+/// How long to wait between failed attempts to connect to a remote.
+///
+/// The delay starts at `initial` and doubles with each failed attempt, but never exceeds `max`.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// Get the delay before the next attempt after the specified number of failed attempts.
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// The backend talking to a pipewire server, running in its own thread.
+pub struct PipewireBackend {
+    pw_sender: pipewire::channel::Sender<GtkMessage>,
+    pw_thread: RefCell<Option<JoinHandle<()>>>,
+}
+
+impl PipewireBackend {
+    /// Start the pipewire thread.
+    ///
+    /// The thread does not connect to a remote until a [`GtkMessage::Connect`] is sent.
+    ///
+    /// Returns the backend and the receiver of the messages sent by the thread.
+    pub fn spawn(backoff: Backoff) -> (Self, async_channel::Receiver<PipewireMessage>) {
+        // Start the pipewire thread with channels in both directions.
+        let (gtk_sender, gtk_receiver) = async_channel::unbounded();
+        let (pw_sender, pw_receiver) = pipewire::channel::channel();
+        let pw_thread = std::thread::spawn(move || thread_main(gtk_sender, pw_receiver, backoff));
+
+        let backend = Self {
+            pw_sender,
+            pw_thread: RefCell::new(Some(pw_thread)),
+        };
+
+        (backend, gtk_receiver)
+    }
+}
+
+impl Backend for PipewireBackend {
+    fn send(&self, msg: GtkMessage) {
+        if self.pw_sender.send(msg).is_err() {
+            error!("Failed to send message to pipewire thread");
+        }
+    }
+
+    fn terminate(&self) {
+        self.send(GtkMessage::Terminate);
+    }
+
+    fn join(&self) {
+        let Some(pw_thread) = self.pw_thread.take() else {
+            return;
+        };
+
+        if pw_thread.join().is_err() {
+            error!("Pipewire thread panicked");
+        }
+    }
+}
+
 /// The "main" function of the pipewire thread.
-pub(super) fn thread_main(
+fn thread_main(
     gtk_sender: async_channel::Sender<PipewireMessage>,
     mut pw_receiver: pipewire::channel::Receiver<GtkMessage>,
+    backoff: Backoff,
 ) {
     let mainloop = MainLoop::new(None).expect("Failed to create mainloop");
     let context = Rc::new(Context::new(&mainloop).expect("Failed to create context"));
     let loop_state = Rc::new(RefCell::new(LoopState {
         is_stopped: false,
+        has_remote: false,
+        attempt: 0,
         props: properties! {
             "media.category" => "Manager",
         },
     }));
-    let mut is_connecting = false;
+    // Whether a failed attempt to connect was reported since the last successful connection.
+    let mut failed = false;
 
     // Wait PipeWire service to connect from command line arguments.
     let receiver = pw_receiver.attach(mainloop.loop_(), {
@@ -106,16 +344,32 @@ pub(super) fn thread_main(
         let props = loop_state.borrow().props.clone();
         let core = match context.connect(Some(props)) {
             Ok(core) => Rc::new(core),
-            Err(_) => {
-                if !is_connecting {
-                    is_connecting = true;
-                    gtk_sender
-                        .send_blocking(PipewireMessage::Connecting)
-                        .expect("Failed to send message");
-                }
+            Err(e) => {
+                failed = true;
+                let attempt = {
+                    let mut loop_state = loop_state.borrow_mut();
+                    loop_state.attempt += 1;
+                    loop_state.attempt
+                };
+                let retry_in = backoff.delay(attempt);
 
-                // If connection is failed, try to connect again in 200ms
-                let interval = Some(Duration::from_millis(200));
+                let remote = props.get(*keys::REMOTE_NAME).map(ToString::to_string);
+                debug!(
+                    "Failed to connect to remote {:?} (attempt {attempt}): {e}, retrying in {retry_in:?}",
+                    remote
+                );
+                gtk_sender
+                    .send_blocking(PipewireMessage::Connecting {
+                        remote,
+                        attempt,
+                        error: e.to_string(),
+                        retry_in,
+                    })
+                    .expect("Failed to send message");
+
+                // If connection is failed, try to connect again once the backoff delay has passed,
+                // or earlier if a retry is requested.
+                let interval = Some(retry_in);
 
                 let timer = mainloop
                     .loop_()
@@ -140,14 +394,16 @@ pub(super) fn thread_main(
             }
         };
 
-        if is_connecting {
-            is_connecting = false;
+        loop_state.borrow_mut().attempt = 0;
+        if failed {
+            failed = false;
             gtk_sender
                 .send_blocking(PipewireMessage::Connected)
                 .expect("Failed to send message");
         }
 
         let registry = Rc::new(core.get_registry().expect("Failed to get registry"));
+        let sender = GtkSender::new(gtk_sender.clone());
 
         // Keep proxies and their listeners alive so that we can receive info events.
         let proxies = Rc::new(RefCell::new(HashMap::new()));
@@ -155,16 +411,52 @@ pub(super) fn thread_main(
 
         let receiver = pw_receiver.attach(mainloop.loop_(), {
             clone!(@strong mainloop, @weak core, @weak registry, @strong state, @strong loop_state => move |msg| match msg {
-                GtkMessage::ToggleLink { port_from, port_to } => toggle_link(port_from, port_to, &core, &registry, &state),
+                GtkMessage::ToggleLink { port_from, port_to, options } => toggle_link(port_from, port_to, options, &core, &registry, &proxies, &state),
+                GtkMessage::SetNodeDescription { id, description } => set_node_description(id, &description, &proxies),
                 GtkMessage::Terminate | GtkMessage::Connect(_) => {
                     loop_state.borrow_mut().handle_message(msg);
                     mainloop.quit();
                 }
+                GtkMessage::Retry => {
+                    // Already connected, nothing to retry.
+                }
             })
         });
 
+        // The roundtrip we are waiting for to find out whether the initial state was received completely.
+        let pending_sync = Rc::new(Cell::new(None));
+        // Number of collected messages when the pending roundtrip was started.
+        let synced_len = Rc::new(Cell::new(Some(0)));
+
         let _listener = core
             .add_listener_local()
+            .done(
+                clone!(@strong sender, @weak core, @strong pending_sync, @strong synced_len => move |id, seq| {
+                    if id != PW_ID_CORE || pending_sync.get() != Some(seq) {
+                        return;
+                    }
+
+                    // Objects that were bound during the roundtrip only send their info now,
+                    // which may in turn request more info, e.g. the formats of ports.
+                    // So keep waiting for roundtrips until one of them does not bring up anything new.
+                    let len = sender.snapshot_len();
+                    if len == synced_len.get() {
+                        pending_sync.set(None);
+                        sender.send_snapshot();
+                        return;
+                    }
+
+                    synced_len.set(len);
+                    match core.sync(0) {
+                        Ok(seq) => pending_sync.set(Some(seq)),
+                        Err(e) => {
+                            warn!("Failed to sync with remote, sending incomplete snapshot: {e}");
+                            pending_sync.set(None);
+                            sender.send_snapshot();
+                        }
+                    }
+                }),
+            )
             .error(
                 clone!(@strong mainloop, @strong gtk_sender => move |id, _seq, res, message| {
                     if id != PW_ID_CORE {
@@ -185,23 +477,30 @@ pub(super) fn thread_main(
 
         let _listener = registry
             .add_listener_local()
-            .global(clone!(@strong gtk_sender, @weak registry, @strong proxies, @strong state =>
+            .global(clone!(@strong sender, @weak registry, @strong proxies, @strong state =>
                 move |global| match global.type_ {
-                    ObjectType::Node => handle_node(global, &gtk_sender, &registry, &proxies, &state),
-                    ObjectType::Port => handle_port(global, &gtk_sender, &registry, &proxies, &state),
-                    ObjectType::Link => handle_link(global, &gtk_sender, &registry, &proxies, &state),
+                    ObjectType::Node => handle_node(global, &sender, &registry, &proxies, &state),
+                    ObjectType::Port => handle_port(global, &sender, &registry, &proxies, &state),
+                    ObjectType::Link => handle_link(global, &sender, &registry, &proxies, &state),
+                    ObjectType::Metadata => handle_metadata(global, &sender, &registry, &proxies, &state),
                     _ => {
                         // Other objects are not interesting to us
                     }
                 }
             ))
-            .global_remove(clone!(@strong gtk_sender, @strong proxies, @strong state => move |id| {
+            .global_remove(clone!(@strong sender, @strong proxies, @strong state => move |id| {
                 if let Some(item) = state.borrow_mut().remove(id) {
-                    gtk_sender.send_blocking(match item {
+                    let msg = match item {
                         Item::Node { .. } => PipewireMessage::NodeRemoved {id},
                         Item::Port { node_id } => PipewireMessage::PortRemoved {id, node_id},
                         Item::Link { .. } => PipewireMessage::LinkRemoved {id},
-                    }).expect("Failed to send message");
+                        // The GTK thread was never told about these.
+                        Item::Quarantined | Item::OrphanedPort { .. } | Item::Metadata => {
+                            proxies.borrow_mut().remove(&id);
+                            return;
+                        }
+                    };
+                    sender.send(msg);
                 } else {
                     warn!(
                         "Attempted to remove item with id {} that is not saved in state",
@@ -213,6 +512,15 @@ pub(super) fn thread_main(
             }))
             .register();
 
+        // The remote answers the roundtrip once it sent us all objects that existed when we connected.
+        match core.sync(0) {
+            Ok(seq) => pending_sync.set(Some(seq)),
+            Err(e) => {
+                warn!("Failed to sync with remote, sending objects as they arrive: {e}");
+                sender.send_snapshot();
+            }
+        }
+
         mainloop.run();
         pw_receiver = receiver.deattach();
 
@@ -222,7 +530,31 @@ pub(super) fn thread_main(
     }
 }
 
+/// Ignore a malformed object, notifying the GTK thread about it.
+///
+/// The object is remembered as quarantined, so that further events about it are ignored too.
+fn quarantine(id: u32, reason: String, sender: &GtkSender, state: &mut State) {
+    warn!("Ignoring malformed object with id {id}: {reason}");
+
+    state.insert(id, Item::Quarantined);
+
+    sender.send(PipewireMessage::MalformedObject { id, reason });
+}
+
+/// Get the id of the node a port belongs to from the properties of the port.
+fn parse_port_node_id(props: &DictRef) -> Result<u32, String> {
+    let node_id = props.get("node.id").ok_or("Port has no node.id property")?;
+
+    node_id
+        .parse()
+        .map_err(|e| format!("Could not parse node.id property \"{node_id}\": {e}"))
+}
+
 /// Get the nicest possible name for the node, using a fallback chain of possible name attributes
+///
+/// A label that the user gave to the node in Helvum takes precedence over all of these,
+/// but as labels are stored by the GTK thread, they are applied there.
+/// A label that was written back to the node ends up in its `node.description`, so it is picked up here as well.
 fn get_node_name(props: &DictRef) -> &str {
     props
         .get(&keys::NODE_DESCRIPTION)
@@ -231,18 +563,68 @@ fn get_node_name(props: &DictRef) -> &str {
         .unwrap_or_default()
 }
 
+/// Get a name for the node that stays the same when the node is recreated, e.g. after a restart.
+///
+/// Unlike the pipewire id, this can be used to remember things about the node.
+/// Streams usually have no `object.path`, so several streams of an application may share the same name,
+/// which [`unique_node_identity`] tells apart.
+fn get_node_identity(props: &DictRef) -> &str {
+    props
+        .get(&keys::OBJECT_PATH)
+        .or_else(|| props.get(&keys::NODE_NAME))
+        .unwrap_or_default()
+}
+
+/// Make the identity of a new node unique among the current nodes.
+///
+/// Nodes that share an identity, like the streams of a browser that are all named after it,
+/// are numbered in the order they appear, e.g. "Firefox", "Firefox#2" and "Firefox#3".
+/// The number of a removed node is given to the next node with the same identity.
+/// This keeps the identity of each stream the same as long as they are created in the same order.
+fn unique_node_identity(identity: &str, state: &State) -> String {
+    if identity.is_empty() {
+        return String::new();
+    }
+
+    (1..)
+        .map(|instance| match instance {
+            1 => identity.to_string(),
+            _ => format!("{identity}#{instance}"),
+        })
+        .find(|candidate| !state.has_node_identity(candidate))
+        .expect("Some instance number should be free")
+}
+
 /// Handle a new node being added
 fn handle_node(
     node: &GlobalObject<&DictRef>,
-    sender: &async_channel::Sender<PipewireMessage>,
+    sender: &GtkSender,
     registry: &Rc<Registry>,
     proxies: &Rc<RefCell<HashMap<u32, ProxyItem>>>,
     state: &Rc<RefCell<State>>,
 ) {
-    let props = node
-        .props
-        .as_ref()
-        .expect("Node object is missing properties");
+    let Some(props) = node.props.as_ref() else {
+        quarantine(
+            node.id,
+            "Node is missing properties".to_string(),
+            sender,
+            &mut state.borrow_mut(),
+        );
+        return;
+    };
+
+    let proxy: Node = match registry.bind(node) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            quarantine(
+                node.id,
+                format!("Failed to bind to node proxy: {e}"),
+                sender,
+                &mut state.borrow_mut(),
+            );
+            return;
+        }
+    };
 
     let name = get_node_name(props).to_string();
     let media_class = |class: &str| {
@@ -266,17 +648,42 @@ fn handle_node(
         })
         .or_else(|| props.get("media.class").and_then(media_class));
 
-    state.borrow_mut().insert(node.id, Item::Node);
+    let media_class = props.get("media.class").map(ToString::to_string);
+    let is_virtual = props.get("node.virtual") == Some("true");
+    let is_stream = media_class
+        .as_deref()
+        .is_some_and(|class| class.starts_with("Stream/"));
 
-    sender
-        .send_blocking(PipewireMessage::NodeAdded {
-            id: node.id,
-            name,
-            node_type,
-        })
-        .expect("Failed to send message");
+    let identity = unique_node_identity(get_node_identity(props), &state.borrow());
+
+    state.borrow_mut().insert(
+        node.id,
+        Item::Node {
+            is_stream,
+            serial: props.get("object.serial").map(ToString::to_string),
+            node_name: props.get(&keys::NODE_NAME).map(ToString::to_string),
+            name: name.clone(),
+            identity: identity.clone(),
+        },
+    );
+
+    sender.send(PipewireMessage::NodeAdded {
+        id: node.id,
+        identity,
+        name,
+        node_type,
+        media_class,
+        is_virtual,
+    });
+
+    // Ports that appeared before their node can be announced now.
+    for port_id in release_orphaned_ports(node.id, sender, &mut state.borrow_mut()) {
+        if let Some(ProxyItem::Port { proxy, formats, .. }) = proxies.borrow().get(&port_id) {
+            let pending = std::mem::take(&mut formats.borrow_mut().pending);
+            enum_format_params(proxy, &pending);
+        }
+    }
 
-    let proxy: Node = registry.bind(node).expect("Failed to bind to node proxy");
     let listener = proxy
         .add_listener_local()
         .info(clone!(@strong sender, @strong proxies => move |info| {
@@ -287,7 +694,7 @@ fn handle_node(
     proxies.borrow_mut().insert(
         node.id,
         ProxyItem::Node {
-            _proxy: proxy,
+            proxy,
             _listener: listener,
         },
     );
@@ -295,7 +702,7 @@ fn handle_node(
 
 fn handle_node_info(
     info: &NodeInfoRef,
-    sender: &async_channel::Sender<PipewireMessage>,
+    sender: &GtkSender,
     proxies: &Rc<RefCell<HashMap<u32, ProxyItem>>>,
 ) {
     debug!("Received node info: {:?}", info);
@@ -307,30 +714,45 @@ fn handle_node_info(
         return;
     };
 
-    let props = info.props().expect("NodeInfo object is missing properties");
-    if let Some(media_name) = props.get(&keys::MEDIA_NAME) {
+    let Some(props) = info.props() else {
+        warn!("Received info without properties on node with id {id}");
+        return;
+    };
+    // The name may have changed as well, e.g. when a label was written back to the node description.
+    if info.change_mask().contains(NodeChangeMask::PROPS) {
         let name = get_node_name(props).to_string();
+        let media_name = props.get(&keys::MEDIA_NAME).unwrap_or_default();
 
-        sender
-            .send_blocking(PipewireMessage::NodeNameChanged {
-                id,
-                name,
-                media_name: media_name.to_string(),
-            })
-            .expect("Failed to send message");
+        sender.send(PipewireMessage::NodeNameChanged {
+            id,
+            name,
+            media_name: media_name.to_string(),
+        });
     }
 }
 
 /// Handle a new port being added
 fn handle_port(
     port: &GlobalObject<&DictRef>,
-    sender: &async_channel::Sender<PipewireMessage>,
+    sender: &GtkSender,
     registry: &Rc<Registry>,
     proxies: &Rc<RefCell<HashMap<u32, ProxyItem>>>,
     state: &Rc<RefCell<State>>,
 ) {
     let port_id = port.id;
-    let proxy: Port = registry.bind(port).expect("Failed to bind to port proxy");
+    let formats = Rc::new(RefCell::new(PortFormats::default()));
+    let proxy: Port = match registry.bind(port) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            quarantine(
+                port_id,
+                format!("Failed to bind to port proxy: {e}"),
+                sender,
+                &mut state.borrow_mut(),
+            );
+            return;
+        }
+    };
     let listener = proxy
         .add_listener_local()
         .info(
@@ -338,17 +760,28 @@ fn handle_port(
                 handle_port_info(info, &proxies, &state, &sender);
             }),
         )
-        .param(clone!(@strong sender => move |_, param_id, _, _, param| {
-            if param_id == ParamType::EnumFormat {
-                handle_port_enum_format(port_id, param, &sender)
-            }
-        }))
+        .param(
+            clone!(@strong sender, @strong formats => move |_, param_id, _, _, param| {
+                match param_id {
+                    ParamType::Format => {
+                        formats.borrow_mut().negotiated = param.is_some();
+                        handle_port_format(port_id, param_id, param, &sender);
+                    }
+                    // A late enumerated format must not replace the negotiated one.
+                    ParamType::EnumFormat if !formats.borrow().negotiated => {
+                        handle_port_format(port_id, param_id, param, &sender);
+                    }
+                    _ => {}
+                }
+            }),
+        )
         .register();
 
     proxies.borrow_mut().insert(
         port.id,
         ProxyItem::Port {
             proxy,
+            formats,
             _listener: listener,
         },
     );
@@ -358,79 +791,235 @@ fn handle_port_info(
     info: &PortInfoRef,
     proxies: &Rc<RefCell<HashMap<u32, ProxyItem>>>,
     state: &Rc<RefCell<State>>,
-    sender: &async_channel::Sender<PipewireMessage>,
+    sender: &GtkSender,
 ) {
     debug!("Received port info: {:?}", info);
 
     let id = info.id();
     let proxies = proxies.borrow();
-    let Some(ProxyItem::Port { proxy, .. }) = proxies.get(&id) else {
+    let Some(ProxyItem::Port { proxy, formats, .. }) = proxies.get(&id) else {
         log::error!("Received info on unknown port with id {id}");
         return;
     };
 
     let mut state = state.borrow_mut();
+    let format_params = || {
+        let params = info
+            .params()
+            .iter()
+            .map(|param| (param.id(), param.flags()));
+        formats.borrow_mut().update(params)
+    };
 
-    if let Some(Item::Port { .. }) = state.get(id) {
-        // Info was an update, figure out if we should notify the GTK thread
-        if info.change_mask().contains(PortChangeMask::PARAMS) {
-            // TODO: React to param changes
+    match state.get(id) {
+        Some(Item::Port { .. }) => {
+            // Info was an update, figure out if we should notify the GTK thread
+            if info.change_mask().contains(PortChangeMask::PARAMS) {
+                // The format of the port may have changed, so get the new one.
+                enum_format_params(proxy, &format_params());
+            }
+            return;
         }
-    } else {
-        // First time we get info. We can now notify the gtk thread of a new link.
-        let props = info.props().expect("Port object is missing properties");
-        let name = props.get("port.name").unwrap_or_default().to_string();
-        let node_id: u32 = props
-            .get("node.id")
-            .expect("Port has no node.id property!")
-            .parse()
-            .expect("Could not parse node.id property");
-
-        state.insert(id, Item::Port { node_id });
-
-        let params = info.params();
-        let enum_format_info = params
-            .iter()
-            .find(|param| param.id() == ParamType::EnumFormat);
-        if let Some(enum_format_info) = enum_format_info {
-            if enum_format_info.flags().contains(ParamInfoFlags::READ) {
-                proxy.enum_params(0, Some(ParamType::EnumFormat), 0, u32::MAX);
+        Some(Item::OrphanedPort { .. }) => {
+            if info.change_mask().contains(PortChangeMask::PARAMS) {
+                let params = format_params();
+                hold_back_format_params(&mut formats.borrow_mut(), params);
             }
+            return;
         }
+        Some(Item::Quarantined) => return,
+        _ => {}
+    }
 
-        sender
-            .send_blocking(PipewireMessage::PortAdded {
+    // First time we get info. We can now notify the gtk thread of a new port.
+    let Some(props) = info.props() else {
+        quarantine(
+            id,
+            "Port is missing properties".to_string(),
+            sender,
+            &mut state,
+        );
+        return;
+    };
+
+    let params = format_params();
+    if add_port(id, props, info.direction(), sender, &mut state) {
+        enum_format_params(proxy, &params);
+    } else {
+        hold_back_format_params(&mut formats.borrow_mut(), params);
+    }
+}
+
+/// Announce a new port to the GTK thread.
+///
+/// Ports with a missing or malformed `node.id` are quarantined. Ports whose node is not known yet
+/// are held back until the node appears, see [`release_orphaned_ports`].
+///
+/// Returns whether the port was announced.
+fn add_port(
+    id: u32,
+    props: &DictRef,
+    direction: Direction,
+    sender: &GtkSender,
+    state: &mut State,
+) -> bool {
+    let node_id = match parse_port_node_id(props) {
+        Ok(node_id) => node_id,
+        Err(reason) => {
+            quarantine(id, reason, sender, state);
+            return false;
+        }
+    };
+    let name = props.get("port.name").unwrap_or_default().to_string();
+    let is_monitor = props.get("port.monitor") == Some("true");
+    // MIDI ports use formats like "8 bit raw midi" or "32 bit raw UMP".
+    let is_midi = props.get("format.dsp").is_some_and(|format| {
+        let format = format.to_lowercase();
+        format.contains("midi") || format.contains("ump")
+    });
+    let alias = props.get("port.alias").map(ToString::to_string);
+    let port_index = props.get("port.id").and_then(|index| index.parse().ok());
+    let audio_channel = props.get("audio.channel").map(ToString::to_string);
+    let object_path = props.get("object.path").map(ToString::to_string);
+    let is_physical = props.get("port.physical") == Some("true");
+    let is_terminal = props.get("port.terminal") == Some("true");
+
+    let announcement = PipewireMessage::PortAdded {
+        id,
+        node_id,
+        name,
+        direction,
+        is_monitor,
+        is_midi,
+        alias,
+        port_index,
+        audio_channel,
+        object_path,
+        is_physical,
+        is_terminal,
+    };
+
+    match state.get(node_id) {
+        Some(Item::Node { .. }) => {
+            state.insert(id, Item::Port { node_id });
+            sender.send(announcement);
+            true
+        }
+        Some(Item::Quarantined) => {
+            quarantine(
                 id,
-                node_id,
-                name,
-                direction: info.direction(),
-            })
-            .expect("Failed to send message");
+                format!("Port belongs to malformed node with id {node_id}"),
+                sender,
+                state,
+            );
+            false
+        }
+        _ => {
+            warn!(
+                "Node with id {node_id} of port with id {id} is not known yet, holding port back"
+            );
+            state.insert(
+                id,
+                Item::OrphanedPort {
+                    node_id,
+                    announcement,
+                },
+            );
+            false
+        }
     }
 }
 
-fn handle_port_enum_format(
+/// Announce the ports that were held back until their node appeared.
+///
+/// Returns the ids of the announced ports.
+fn release_orphaned_ports(node_id: u32, sender: &GtkSender, state: &mut State) -> Vec<u32> {
+    state
+        .adopt_orphaned_ports(node_id)
+        .into_iter()
+        .map(|(id, announcement)| {
+            info!("Node with id {node_id} appeared, announcing its port with id {id}");
+            sender.send(announcement);
+            id
+        })
+        .collect()
+}
+
+/// Request the specified format params of the port, as returned by [`PortFormats::update`].
+///
+/// The formats are received by the param listener of the port.
+fn enum_format_params(proxy: &Port, params: &[ParamType]) {
+    for param_id in params {
+        proxy.enum_params(0, Some(*param_id), 0, u32::MAX);
+    }
+}
+
+/// Remember format params that changed while the port is not announced yet,
+/// so that they can be enumerated once it is, see [`release_orphaned_ports`].
+fn hold_back_format_params(formats: &mut PortFormats, params: Vec<ParamType>) {
+    for param_id in params {
+        if !formats.pending.contains(&param_id) {
+            formats.pending.push(param_id);
+        }
+    }
+}
+
+fn handle_port_format(
     port_id: u32,
+    param_id: ParamType,
     param: Option<&pipewire::spa::pod::Pod>,
-    sender: &async_channel::Sender<PipewireMessage>,
+    sender: &GtkSender,
 ) {
-    let media_type = param
-        .and_then(|param| pipewire::spa::param::format_utils::parse_format(param).ok())
+    let parsed =
+        param.and_then(|param| pipewire::spa::param::format_utils::parse_format(param).ok());
+    let media_type = parsed
         .map(|(media_type, _media_subtype)| media_type)
         .unwrap_or(MediaType::Unknown);
 
-    sender
-        .send_blocking(PipewireMessage::PortFormatChanged {
-            id: port_id,
-            media_type,
-        })
-        .expect("Failed to send message")
+    // Only the negotiated format describes what actually flows through the port,
+    // an enumerated format is just one of the possible ones.
+    let format = match (param_id, param, parsed) {
+        (ParamType::Format, Some(param), Some((media_type, media_subtype))) => {
+            Some(describe_format(param, media_type, media_subtype))
+        }
+        _ => None,
+    };
+
+    sender.send(PipewireMessage::PortFormatChanged {
+        id: port_id,
+        media_type,
+        format,
+    });
+}
+
+/// Get a human readable description of a format, e.g. "F32LE, 48000 Hz, 2 channels".
+fn describe_format(
+    param: &pipewire::spa::pod::Pod,
+    media_type: MediaType,
+    media_subtype: MediaSubtype,
+) -> String {
+    if media_type == MediaType::Audio && media_subtype == MediaSubtype::Raw {
+        let mut audio_info = AudioInfoRaw::new();
+        if audio_info.parse(param).is_ok() {
+            let sample_format = format!("{:?}", audio_info.format());
+            return format!(
+                "{}, {} Hz, {} channels",
+                sample_format.trim_start_matches("AudioFormat::"),
+                audio_info.rate(),
+                audio_info.channels()
+            );
+        }
+    }
+
+    format!("{media_subtype:?}")
+        .trim_start_matches("MediaSubtype::")
+        .to_string()
 }
 
 /// Handle a new link being added
 fn handle_link(
     link: &GlobalObject<&DictRef>,
-    sender: &async_channel::Sender<PipewireMessage>,
+    sender: &GtkSender,
     registry: &Rc<Registry>,
     proxies: &Rc<RefCell<HashMap<u32, ProxyItem>>>,
     state: &Rc<RefCell<State>>,
@@ -440,7 +1029,18 @@ fn handle_link(
         link.id
     );
 
-    let proxy: Link = registry.bind(link).expect("Failed to bind to link proxy");
+    let proxy: Link = match registry.bind(link) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            quarantine(
+                link.id,
+                format!("Failed to bind to link proxy: {e}"),
+                sender,
+                &mut state.borrow_mut(),
+            );
+            return;
+        }
+    };
     let listener = proxy
         .add_listener_local()
         .info(clone!(@strong state, @strong sender => move |info| {
@@ -457,11 +1057,7 @@ fn handle_link(
     );
 }
 
-fn handle_link_info(
-    info: &LinkInfoRef,
-    state: &Rc<RefCell<State>>,
-    sender: &async_channel::Sender<PipewireMessage>,
-) {
+fn handle_link_info(info: &LinkInfoRef, state: &Rc<RefCell<State>>, sender: &GtkSender) {
     debug!("Received link info: {:?}", info);
 
     let id = info.id();
@@ -470,46 +1066,233 @@ fn handle_link_info(
     if let Some(Item::Link { .. }) = state.get(id) {
         // Info was an update - figure out if we should notify the gtk thread
         if info.change_mask().contains(LinkChangeMask::STATE) {
-            sender
-                .send_blocking(PipewireMessage::LinkStateChanged {
-                    id,
-                    active: matches!(info.state(), LinkState::Active),
-                })
-                .expect("Failed to send message");
+            let (state, error) = link_state(info);
+            sender.send(PipewireMessage::LinkStateChanged { id, state, error });
         }
         if info.change_mask().contains(LinkChangeMask::FORMAT) {
-            sender
-                .send_blocking(PipewireMessage::LinkFormatChanged {
-                    id,
-                    media_type: get_link_media_type(info),
-                })
-                .expect("Failed to send message");
+            sender.send(PipewireMessage::LinkFormatChanged {
+                id,
+                media_type: get_link_media_type(info),
+            });
         }
     } else {
         // First time we get info. We can now notify the gtk thread of a new link.
         let port_from = info.output_port_id();
         let port_to = info.input_port_id();
 
+        let prop_is_set = |key: &str| {
+            info.props()
+                .is_some_and(|props| matches!(props.get(key), Some("true" | "1")))
+        };
+        let passive = prop_is_set("link.passive");
+        let linger = prop_is_set("object.linger");
+        let created_by_helvum = prop_is_set(CREATED_BY_HELVUM_KEY);
+
         state.insert(id, Item::Link { port_from, port_to });
 
-        sender
-            .send_blocking(PipewireMessage::LinkAdded {
-                id,
-                port_from,
-                port_to,
-                active: matches!(info.state(), LinkState::Active),
-                media_type: get_link_media_type(info),
+        let (link_state, error) = link_state(info);
+        sender.send(PipewireMessage::LinkAdded {
+            id,
+            port_from,
+            port_to,
+            state: link_state,
+            error,
+            passive,
+            linger,
+            created_by_helvum,
+            media_type: get_link_media_type(info),
+        });
+    }
+}
+
+/// Get the state of a link, together with the error message if the link failed.
+fn link_state(info: &LinkInfoRef) -> (crate::LinkState, Option<String>) {
+    let state = match info.state() {
+        LinkState::Error(error) => return (crate::LinkState::Error, Some(error.to_string())),
+        LinkState::Unlinked => crate::LinkState::Unlinked,
+        LinkState::Init => crate::LinkState::Init,
+        LinkState::Negotiating => crate::LinkState::Negotiating,
+        LinkState::Allocating => crate::LinkState::Allocating,
+        LinkState::Paused => crate::LinkState::Paused,
+        LinkState::Active => crate::LinkState::Active,
+    };
+
+    (state, None)
+}
+
+/// Handle a new metadata object being added.
+///
+/// Only the `default` metadata is of interest, as the session manager reads the targets of streams from it.
+fn handle_metadata(
+    metadata: &GlobalObject<&DictRef>,
+    sender: &GtkSender,
+    registry: &Rc<Registry>,
+    proxies: &Rc<RefCell<HashMap<u32, ProxyItem>>>,
+    state: &Rc<RefCell<State>>,
+) {
+    let is_default = metadata
+        .props
+        .as_ref()
+        .is_some_and(|props| props.get("metadata.name") == Some("default"));
+    if !is_default {
+        return;
+    }
+
+    let proxy: Metadata = match registry.bind(metadata) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            warn!("Failed to bind to default metadata, stream targets will not be shown: {e}");
+            return;
+        }
+    };
+
+    state.borrow_mut().insert(metadata.id, Item::Metadata);
+
+    let listener = proxy
+        .add_listener_local()
+        .property(
+            clone!(@strong sender, @strong state => move |subject, key, _type, value| {
+                handle_metadata_property(subject, key, value, &sender, &state);
+                0
+            }),
+        )
+        .register();
+
+    proxies.borrow_mut().insert(
+        metadata.id,
+        ProxyItem::Metadata {
+            proxy,
+            _listener: listener,
+        },
+    );
+}
+
+fn handle_metadata_property(
+    subject: u32,
+    key: Option<&str>,
+    value: Option<&str>,
+    sender: &GtkSender,
+    state: &Rc<RefCell<State>>,
+) {
+    let mut state = state.borrow_mut();
+
+    let Some(key) = key else {
+        // All properties of the subject were removed.
+        if state.clear_stream_targets_of(subject) {
+            sender.send(PipewireMessage::NodeTargetChanged {
+                id: subject,
+                target: None,
+            });
+        }
+        return;
+    };
+
+    if key != TARGET_OBJECT_KEY && key != TARGET_NODE_KEY {
+        return;
+    }
+
+    debug!("Target of stream with id {subject} set to {value:?} under key {key}");
+
+    // A target of -1 is used to unset the target as well.
+    let value = value.filter(|value| *value != "-1");
+    state.set_stream_target(subject, key, value.map(ToString::to_string));
+
+    sender.send(PipewireMessage::NodeTargetChanged {
+        id: subject,
+        target: stream_target_name(subject, &state),
+    });
+}
+
+/// Get the name of the node that the specified stream is pinned to in the metadata, if any.
+fn stream_target_name(stream: u32, state: &State) -> Option<String> {
+    let node_name = |id| match state.get(id) {
+        Some(Item::Node { name, .. }) => Some(name.clone()),
+        _ => None,
+    };
+
+    // The session manager prefers `target.object` over the older `target.node`.
+    if let Some(value) = state.stream_target(stream, TARGET_OBJECT_KEY) {
+        let name = state.find_node_by_serial_or_name(value).and_then(node_name);
+        return Some(name.unwrap_or_else(|| value.to_string()));
+    }
+
+    let value = state.stream_target(stream, TARGET_NODE_KEY)?;
+    let name = value.parse().ok().and_then(node_name);
+    Some(name.unwrap_or_else(|| value.to_string()))
+}
+
+/// Find which end of a link between the two nodes should be pinned to the other end.
+///
+/// Returns the ids of the stream node and its target node, or `None` if neither node is a stream.
+fn pinned_stream_of(node_from: u32, node_to: u32, state: &State) -> Option<(u32, u32)> {
+    let is_stream = |id| {
+        matches!(
+            state.get(id),
+            Some(Item::Node {
+                is_stream: true,
+                ..
             })
-            .expect("Failed to send message");
+        )
+    };
+
+    if is_stream(node_from) {
+        Some((node_from, node_to))
+    } else if is_stream(node_to) {
+        Some((node_to, node_from))
+    } else {
+        None
+    }
+}
+
+/// Tell the session manager to keep the stream routed to the target node, or to route it freely again.
+fn set_stream_target(
+    stream: u32,
+    target: Option<u32>,
+    proxies: &Rc<RefCell<HashMap<u32, ProxyItem>>>,
+    state: &State,
+) {
+    let proxies = proxies.borrow();
+    let Some(metadata) = proxies.values().find_map(|item| match item {
+        ProxyItem::Metadata { proxy, .. } => Some(proxy),
+        _ => None,
+    }) else {
+        warn!("No default metadata to pin the target of stream with id {stream} in, ignoring");
+        return;
+    };
+
+    let Some(target) = target else {
+        info!("Unpinning target of stream with id {stream}");
+        metadata.set_property(stream, TARGET_OBJECT_KEY, None, None);
+        metadata.set_property(stream, TARGET_NODE_KEY, None, None);
+        return;
+    };
+
+    info!("Pinning target of stream with id {stream} to node with id {target}");
+    let serial = match state.get(target) {
+        Some(Item::Node { serial, .. }) => serial.as_deref(),
+        _ => None,
+    };
+    if let Some(serial) = serial {
+        metadata.set_property(stream, TARGET_OBJECT_KEY, Some("Spa:Id"), Some(serial));
     }
+    metadata.set_property(
+        stream,
+        TARGET_NODE_KEY,
+        Some("Spa:Id"),
+        Some(&target.to_string()),
+    );
 }
 
 /// Toggle a link between the two specified ports.
+///
+/// If the link does not exist yet, it is created with the specified options.
 fn toggle_link(
     port_from: u32,
     port_to: u32,
+    options: LinkOptions,
     core: &Rc<Core>,
     registry: &Rc<Registry>,
+    proxies: &Rc<RefCell<HashMap<u32, ProxyItem>>>,
     state: &Rc<RefCell<State>>,
 ) {
     let state = state.borrow_mut();
@@ -518,18 +1301,34 @@ fn toggle_link(
 
         // FIXME: Handle error
         registry.destroy_global(id);
+
+        // Let the session manager route the stream freely again, if it was pinned to the other end of this link.
+        if options.pin_target {
+            let pinned = state
+                .get_node_of_port(port_from)
+                .zip(state.get_node_of_port(port_to))
+                .and_then(|(node_from, node_to)| pinned_stream_of(node_from, node_to, &state));
+            if let Some((stream, target)) = pinned {
+                let target = target.to_string();
+                if state.stream_target(stream, TARGET_NODE_KEY) == Some(target.as_str()) {
+                    set_stream_target(stream, None, proxies, &state);
+                }
+            }
+        }
     } else {
         info!(
             "Requesting creation of link from port id:{} to port id:{}",
             port_from, port_to
         );
 
-        let node_from = state
-            .get_node_of_port(port_from)
-            .expect("Requested port not in state");
-        let node_to = state
-            .get_node_of_port(port_to)
-            .expect("Requested port not in state");
+        // The ports may have been removed in the meantime, before the GTK thread was notified.
+        let (Some(node_from), Some(node_to)) = (
+            state.get_node_of_port(port_from),
+            state.get_node_of_port(port_to),
+        ) else {
+            warn!("Requested link between ports that are not in state, ignoring");
+            return;
+        };
 
         if let Err(e) = core.create_object::<Link>(
             "link-factory",
@@ -538,14 +1337,65 @@ fn toggle_link(
                 "link.output.port" => port_from.to_string(),
                 "link.input.node" => node_to.to_string(),
                 "link.input.port" => port_to.to_string(),
-                "object.linger" => "1"
+                "object.linger" => if options.linger { "1" } else { "0" },
+                "link.passive" => if options.passive { "true" } else { "false" },
+                CREATED_BY_HELVUM_KEY => "true"
             },
         ) {
             warn!("Failed to create link: {}", e);
+            return;
+        }
+
+        if options.pin_target {
+            if let Some((stream, target)) = pinned_stream_of(node_from, node_to, &state) {
+                set_stream_target(stream, Some(target), proxies, &state);
+            }
         }
     }
 }
 
+/// Write a new description to a node by setting its `node.description` param.
+///
+/// Only nodes that accept generic params, like most device and stream nodes, apply the change.
+fn set_node_description(
+    id: u32,
+    description: &str,
+    proxies: &Rc<RefCell<HashMap<u32, ProxyItem>>>,
+) {
+    let proxies = proxies.borrow();
+    let Some(ProxyItem::Node { proxy, .. }) = proxies.get(&id) else {
+        warn!("Requested new description for unknown node with id {id}, ignoring");
+        return;
+    };
+
+    let value = Value::Object(Object {
+        type_: SpaTypes::ObjectParamProps.as_raw(),
+        id: ParamType::Props.as_raw(),
+        properties: vec![Property {
+            key: SPA_PROP_params,
+            flags: PropertyFlags::empty(),
+            value: Value::Struct(vec![
+                Value::String("node.description".to_string()),
+                Value::String(description.to_string()),
+            ]),
+        }],
+    });
+    let bytes = match PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &value) {
+        Ok((cursor, _)) => cursor.into_inner(),
+        Err(e) => {
+            warn!("Failed to serialize description of node with id {id}: {e:?}");
+            return;
+        }
+    };
+    let Some(pod) = Pod::from_bytes(&bytes) else {
+        warn!("Failed to create props param for node with id {id}");
+        return;
+    };
+
+    info!("Setting description of node with id {id} to \"{description}\"");
+    proxy.set_param(ParamType::Props, 0, pod);
+}
+
 fn get_link_media_type(link_info: &LinkInfoRef) -> MediaType {
     let media_type = link_info
         .format()
@@ -555,3 +1405,438 @@ fn get_link_media_type(link_info: &LinkInfoRef) -> MediaType {
 
     media_type
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port_props(node_id: Option<&str>) -> Properties {
+        let mut props = properties! {
+            "port.name" => "playback_FL",
+        };
+        if let Some(node_id) = node_id {
+            props.insert("node.id", node_id);
+        }
+        props
+    }
+
+    fn node() -> Item {
+        named_node("Node")
+    }
+
+    fn named_node(identity: &str) -> Item {
+        Item::Node {
+            is_stream: false,
+            serial: None,
+            node_name: None,
+            name: "Node".to_string(),
+            identity: identity.to_string(),
+        }
+    }
+
+    /// Create a sender that collects the messages sent to it, as it does before the initial sync.
+    fn sender() -> GtkSender {
+        let (sender, _receiver) = async_channel::unbounded();
+        GtkSender::new(sender)
+    }
+
+    /// Take the messages that were sent so far.
+    fn sent(sender: &GtkSender) -> Vec<PipewireMessage> {
+        sender
+            .snapshot
+            .borrow_mut()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    const READ: ParamInfoFlags = ParamInfoFlags::READ;
+    const READ_CHANGED: ParamInfoFlags = ParamInfoFlags::READ.union(ParamInfoFlags::SERIAL);
+
+    #[test]
+    fn readable_format_params_are_enumerated_on_first_info() {
+        let mut formats = PortFormats::default();
+
+        assert_eq!(
+            formats.update([
+                (ParamType::EnumFormat, READ),
+                (ParamType::Format, READ),
+                (ParamType::Buffers, READ),
+            ]),
+            vec![ParamType::EnumFormat, ParamType::Format]
+        );
+    }
+
+    #[test]
+    fn format_params_are_only_enumerated_when_changed() {
+        let mut formats = PortFormats::default();
+        formats.update([(ParamType::EnumFormat, READ), (ParamType::Format, READ)]);
+
+        assert!(formats
+            .update([(ParamType::EnumFormat, READ), (ParamType::Format, READ)])
+            .is_empty());
+        assert_eq!(
+            formats.update([
+                (ParamType::EnumFormat, READ),
+                (ParamType::Format, READ_CHANGED)
+            ]),
+            vec![ParamType::Format]
+        );
+        assert_eq!(
+            formats.update([
+                (ParamType::EnumFormat, READ_CHANGED),
+                (ParamType::Format, READ_CHANGED)
+            ]),
+            vec![ParamType::EnumFormat]
+        );
+    }
+
+    #[test]
+    fn enumerated_formats_are_used_when_format_is_cleared() {
+        let mut formats = PortFormats::default();
+        formats.update([(ParamType::EnumFormat, READ), (ParamType::Format, READ)]);
+        formats.negotiated = true;
+
+        assert_eq!(
+            formats.update([
+                (ParamType::EnumFormat, READ),
+                (ParamType::Format, ParamInfoFlags::empty()),
+            ]),
+            vec![ParamType::EnumFormat]
+        );
+        assert!(!formats.negotiated);
+    }
+
+    #[test]
+    fn changed_format_params_are_held_back_once() {
+        let mut formats = PortFormats::default();
+
+        let params = formats.update([(ParamType::EnumFormat, READ), (ParamType::Format, READ)]);
+        hold_back_format_params(&mut formats, params);
+        let params = formats.update([(ParamType::EnumFormat, READ_CHANGED)]);
+        hold_back_format_params(&mut formats, params);
+
+        assert_eq!(
+            formats.pending,
+            vec![ParamType::EnumFormat, ParamType::Format]
+        );
+    }
+
+    #[test]
+    fn parse_port_node_id_accepts_valid_id() {
+        assert_eq!(parse_port_node_id(port_props(Some("42")).dict()), Ok(42));
+    }
+
+    #[test]
+    fn parse_port_node_id_rejects_missing_id() {
+        assert!(parse_port_node_id(port_props(None).dict()).is_err());
+    }
+
+    #[test]
+    fn parse_port_node_id_rejects_unparsable_id() {
+        for node_id in ["", "node", "-1", "4294967296"] {
+            assert!(
+                parse_port_node_id(port_props(Some(node_id)).dict()).is_err(),
+                "{node_id:?} should not be accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn port_without_node_id_is_quarantined() {
+        let sender = sender();
+        let mut state = State::new();
+
+        let props = port_props(None);
+        assert!(!add_port(
+            2,
+            props.dict(),
+            Direction::Input,
+            &sender,
+            &mut state
+        ));
+
+        assert!(matches!(state.get(2), Some(Item::Quarantined)));
+        assert!(matches!(
+            sent(&sender).as_slice(),
+            [PipewireMessage::MalformedObject { id: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn port_with_unparsable_node_id_is_quarantined() {
+        let sender = sender();
+        let mut state = State::new();
+        state.insert(1, node());
+
+        let props = port_props(Some("one"));
+        assert!(!add_port(
+            2,
+            props.dict(),
+            Direction::Input,
+            &sender,
+            &mut state
+        ));
+
+        assert!(matches!(state.get(2), Some(Item::Quarantined)));
+        assert!(matches!(
+            sent(&sender).as_slice(),
+            [PipewireMessage::MalformedObject { id: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn port_of_known_node_is_announced() {
+        let sender = sender();
+        let mut state = State::new();
+        state.insert(1, node());
+
+        let props = port_props(Some("1"));
+        assert!(add_port(
+            2,
+            props.dict(),
+            Direction::Output,
+            &sender,
+            &mut state
+        ));
+
+        assert_eq!(state.get_node_of_port(2), Some(1));
+        assert!(matches!(
+            sent(&sender).as_slice(),
+            [PipewireMessage::PortAdded {
+                id: 2,
+                node_id: 1,
+                direction: Direction::Output,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn port_of_quarantined_node_is_quarantined() {
+        let sender = sender();
+        let mut state = State::new();
+        quarantine(
+            1,
+            "Node is missing properties".to_string(),
+            &sender,
+            &mut state,
+        );
+        sent(&sender);
+
+        let props = port_props(Some("1"));
+        assert!(!add_port(
+            2,
+            props.dict(),
+            Direction::Input,
+            &sender,
+            &mut state
+        ));
+
+        assert!(matches!(state.get(2), Some(Item::Quarantined)));
+        assert!(matches!(
+            sent(&sender).as_slice(),
+            [PipewireMessage::MalformedObject { id: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn orphaned_port_is_announced_once_its_node_appears() {
+        let sender = sender();
+        let mut state = State::new();
+
+        let props = port_props(Some("1"));
+        assert!(!add_port(
+            2,
+            props.dict(),
+            Direction::Input,
+            &sender,
+            &mut state
+        ));
+        assert!(matches!(
+            state.get(2),
+            Some(Item::OrphanedPort { node_id: 1, .. })
+        ));
+        assert!(sent(&sender).is_empty());
+
+        // Other nodes don't release the port.
+        state.insert(3, node());
+        assert!(release_orphaned_ports(3, &sender, &mut state).is_empty());
+        assert!(sent(&sender).is_empty());
+
+        state.insert(1, node());
+        assert_eq!(release_orphaned_ports(1, &sender, &mut state), vec![2]);
+        assert_eq!(state.get_node_of_port(2), Some(1));
+        assert!(matches!(
+            sent(&sender).as_slice(),
+            [PipewireMessage::PortAdded {
+                id: 2,
+                node_id: 1,
+                ..
+            }]
+        ));
+
+        // The port is only announced once.
+        assert!(release_orphaned_ports(1, &sender, &mut state).is_empty());
+    }
+
+    #[test]
+    fn quarantined_object_is_released_when_removed() {
+        let sender = sender();
+        let mut state = State::new();
+        state.insert(1, node());
+
+        let props = port_props(None);
+        add_port(2, props.dict(), Direction::Input, &sender, &mut state);
+        assert!(matches!(state.remove(2), Some(Item::Quarantined)));
+        assert!(state.get(2).is_none());
+        sent(&sender);
+
+        // The id may be reused by a well-formed port afterwards.
+        let props = port_props(Some("1"));
+        assert!(add_port(
+            2,
+            props.dict(),
+            Direction::Input,
+            &sender,
+            &mut state
+        ));
+        assert!(matches!(
+            sent(&sender).as_slice(),
+            [PipewireMessage::PortAdded { id: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn messages_are_batched_until_initial_sync() {
+        let (sender, receiver) = async_channel::unbounded();
+        let sender = GtkSender::new(sender);
+
+        sender.send(PipewireMessage::NodeRemoved { id: 1 });
+        sender.send(PipewireMessage::NodeRemoved { id: 2 });
+        assert!(receiver.is_empty());
+        assert_eq!(sender.snapshot_len(), Some(2));
+
+        sender.send_snapshot();
+        assert_eq!(sender.snapshot_len(), None);
+        sender.send(PipewireMessage::NodeRemoved { id: 3 });
+        // Sending the snapshot again does nothing.
+        sender.send_snapshot();
+
+        let Ok(PipewireMessage::Snapshot(snapshot)) = receiver.try_recv() else {
+            panic!("Snapshot should be sent first");
+        };
+        assert!(matches!(
+            snapshot.as_slice(),
+            [
+                PipewireMessage::NodeRemoved { id: 1 },
+                PipewireMessage::NodeRemoved { id: 2 }
+            ]
+        ));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(PipewireMessage::NodeRemoved { id: 3 })
+        ));
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn removing_all_properties_clears_only_targets_of_subject() {
+        let sender = sender();
+        let state = Rc::new(RefCell::new(State::new()));
+
+        handle_metadata_property(5, Some(TARGET_OBJECT_KEY), Some("42"), &sender, &state);
+        handle_metadata_property(5, Some(TARGET_NODE_KEY), Some("43"), &sender, &state);
+        handle_metadata_property(6, Some(TARGET_OBJECT_KEY), Some("44"), &sender, &state);
+        sent(&sender);
+
+        handle_metadata_property(5, None, None, &sender, &state);
+
+        assert!(matches!(
+            sent(&sender).as_slice(),
+            [PipewireMessage::NodeTargetChanged {
+                id: 5,
+                target: None
+            }]
+        ));
+        let state = state.borrow();
+        assert_eq!(state.stream_target(5, TARGET_OBJECT_KEY), None);
+        assert_eq!(state.stream_target(5, TARGET_NODE_KEY), None);
+        assert_eq!(state.stream_target(6, TARGET_OBJECT_KEY), Some("44"));
+    }
+
+    #[test]
+    fn removing_all_properties_of_subject_without_target_is_ignored() {
+        let sender = sender();
+        let state = Rc::new(RefCell::new(State::new()));
+
+        handle_metadata_property(6, Some(TARGET_OBJECT_KEY), Some("44"), &sender, &state);
+        sent(&sender);
+
+        handle_metadata_property(5, None, None, &sender, &state);
+
+        assert!(sent(&sender).is_empty());
+        assert_eq!(
+            state.borrow().stream_target(6, TARGET_OBJECT_KEY),
+            Some("44")
+        );
+    }
+
+    #[test]
+    fn targets_of_removed_stream_are_forgotten() {
+        let sender = sender();
+        let state = Rc::new(RefCell::new(State::new()));
+        state.borrow_mut().insert(5, node());
+
+        handle_metadata_property(5, Some(TARGET_OBJECT_KEY), Some("42"), &sender, &state);
+        handle_metadata_property(5, Some(TARGET_NODE_KEY), Some("43"), &sender, &state);
+        state.borrow_mut().remove(5);
+
+        // A new node reusing the id starts without a target.
+        state.borrow_mut().insert(5, node());
+        let state = state.borrow();
+        assert_eq!(state.stream_target(5, TARGET_OBJECT_KEY), None);
+        assert_eq!(state.stream_target(5, TARGET_NODE_KEY), None);
+    }
+
+    #[test]
+    fn selecting_remote_resets_failed_attempts() {
+        let mut loop_state = LoopState {
+            is_stopped: false,
+            has_remote: true,
+            attempt: 5,
+            props: Properties::new(),
+        };
+
+        assert!(loop_state.handle_message(GtkMessage::Connect(Some("pipewire-1".to_string()))));
+        assert_eq!(loop_state.attempt, 0);
+    }
+
+    #[test]
+    fn nodes_sharing_identity_are_numbered() {
+        let mut state = State::new();
+
+        assert_eq!(unique_node_identity("Firefox", &state), "Firefox");
+        state.insert(1, named_node("Firefox"));
+        assert_eq!(unique_node_identity("Firefox", &state), "Firefox#2");
+        state.insert(2, named_node("Firefox#2"));
+        assert_eq!(unique_node_identity("Firefox", &state), "Firefox#3");
+
+        // Other identities are not affected.
+        assert_eq!(unique_node_identity("Chromium", &state), "Chromium");
+        // Nodes without identity stay without one.
+        assert_eq!(unique_node_identity("", &state), "");
+    }
+
+    #[test]
+    fn number_of_removed_node_is_reused() {
+        let mut state = State::new();
+        state.insert(1, named_node("Firefox"));
+        state.insert(2, named_node("Firefox#2"));
+        state.insert(3, named_node("Firefox#3"));
+
+        state.remove(2);
+
+        assert_eq!(unique_node_identity("Firefox", &state), "Firefox#2");
+    }
+}
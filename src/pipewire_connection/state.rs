@@ -16,10 +16,23 @@
 
 use std::collections::HashMap;
 
+use crate::PipewireMessage;
+
 /// Any pipewire item we need to keep track of.
 /// These will be saved in the `State` struct associated with their id.
 pub(super) enum Item {
-    Node,
+    Node {
+        /// Whether the node is a stream of an application, which the session manager links to a device.
+        is_stream: bool,
+        /// The `object.serial` property, which the `target.object` metadata of streams refers to nodes by.
+        serial: Option<String>,
+        /// The `node.name` property, which the `target.object` metadata may also refer to nodes by.
+        node_name: Option<String>,
+        /// The name that is shown to the user.
+        name: String,
+        /// The identity that the node was announced with, see [`PipewireMessage::NodeAdded`].
+        identity: String,
+    },
     Port {
         // Save the id of the node this is on so we can remove the port from it
         // when it is deleted.
@@ -29,6 +42,18 @@ pub(super) enum Item {
         port_from: u32,
         port_to: u32,
     },
+    /// A port whose node is not known yet, which is announced to the GTK thread once the node appears.
+    OrphanedPort {
+        node_id: u32,
+        /// The message that announces the port.
+        announcement: PipewireMessage,
+    },
+    /// The metadata object that the session manager reads the targets of streams from.
+    Metadata,
+    /// An object that was malformed and is ignored.
+    ///
+    /// It is kept track of so that further events regarding it are ignored as well.
+    Quarantined,
 }
 
 /// This struct keeps track of any relevant items and stores them under their IDs.
@@ -40,6 +65,8 @@ pub(super) struct State {
     items: HashMap<u32, Item>,
     /// Map `(output port id, input port id)` tuples to the id of the link that connects them.
     links: HashMap<(u32, u32), u32>,
+    /// Map `(stream node id, metadata key)` tuples to the target that is set for the stream under that key.
+    stream_targets: HashMap<(u32, String), String>,
 }
 
 impl State {
@@ -73,6 +100,8 @@ impl State {
     /// Remove the item with the specified id, returning it if it exists.
     pub fn remove(&mut self, id: u32) -> Option<Item> {
         let removed = self.items.remove(&id);
+        // A new object that reuses the id must not inherit the targets of the removed one.
+        self.clear_stream_targets_of(id);
 
         if let Some(Item::Link { port_from, port_to }) = removed {
             self.links.remove(&(port_from, port_to));
@@ -89,4 +118,69 @@ impl State {
             None
         }
     }
+
+    /// Turn the orphaned ports of a node that just appeared into regular ports.
+    ///
+    /// Returns the ids of the ports together with the messages that announce them, ordered by port id.
+    pub fn adopt_orphaned_ports(&mut self, node_id: u32) -> Vec<(u32, PipewireMessage)> {
+        let mut orphans: Vec<u32> = self
+            .items
+            .iter()
+            .filter(
+                |(_, item)| matches!(item, Item::OrphanedPort { node_id: n, .. } if *n == node_id),
+            )
+            .map(|(id, _)| *id)
+            .collect();
+        orphans.sort_unstable();
+
+        orphans
+            .into_iter()
+            .filter_map(|id| match self.items.insert(id, Item::Port { node_id }) {
+                Some(Item::OrphanedPort { announcement, .. }) => Some((id, announcement)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Remember the value of a metadata key that sets the target of a stream, `None` if it was removed.
+    pub fn set_stream_target(&mut self, stream: u32, key: &str, value: Option<String>) {
+        match value {
+            Some(value) => self.stream_targets.insert((stream, key.to_string()), value),
+            None => self.stream_targets.remove(&(stream, key.to_string())),
+        };
+    }
+
+    /// Get the value of a metadata key that sets the target of a stream.
+    pub fn stream_target(&self, stream: u32, key: &str) -> Option<&str> {
+        self.stream_targets
+            .get(&(stream, key.to_string()))
+            .map(String::as_str)
+    }
+
+    /// Forget the targets that are set for the stream under any key, returning whether it had one.
+    pub fn clear_stream_targets_of(&mut self, stream: u32) -> bool {
+        let count = self.stream_targets.len();
+        self.stream_targets
+            .retain(|(target_stream, _), _| *target_stream != stream);
+        self.stream_targets.len() != count
+    }
+
+    /// Whether one of the current nodes has the specified identity.
+    pub fn has_node_identity(&self, identity: &str) -> bool {
+        self.items.values().any(|item| {
+            matches!(item, Item::Node { identity: node_identity, .. } if node_identity == identity)
+        })
+    }
+
+    /// Find the node that a `target.object` metadata value refers to, either by its serial or by its name.
+    pub fn find_node_by_serial_or_name(&self, value: &str) -> Option<u32> {
+        self.items.iter().find_map(|(id, item)| match item {
+            Item::Node {
+                serial, node_name, ..
+            } if serial.as_deref() == Some(value) || node_name.as_deref() == Some(value) => {
+                Some(*id)
+            }
+            _ => None,
+        })
+    }
 }
@@ -16,7 +16,7 @@
 
 use adw::{
     gio,
-    glib::{self, clone},
+    glib::{self, clone, subclass::Signal},
     gtk::{
         self, cairo,
         graphene::{self, Point},
@@ -26,17 +26,35 @@ use adw::{
     subclass::prelude::*,
 };
 
-use std::cmp::Ordering;
-
-use super::{Link, Node, Port};
-use crate::NodeType;
+use pipewire::spa::utils::Direction;
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::{HashSet, VecDeque},
+    rc::Rc,
+};
 
-const CANVAS_SIZE: f64 = 5000.0;
+use super::{
+    link_options_for_modifiers,
+    routing::{obstacle_columns, route_area, route_link, LinkPath, Obstacles},
+    Link, LinkStyle, Node, Port,
+};
+use crate::{LinkState, NodeType};
+
+/// Empty space around the nodes that is part of the canvas, so that nodes can be moved outwards.
+const CANVAS_MARGIN: f32 = 500.0;
+/// Space to leave around nodes when zooming to fit them into the view.
+const ZOOM_TO_FIT_PADDING: f32 = 20.0;
+/// Space around the control points of a link that its line and badge may extend into, in canvas-space.
+const LINK_CULL_MARGIN: f32 = 20.0;
+/// Dash pattern that active links are drawn with while they are animated.
+const LINK_ANIMATION_DASHES: [f64; 2] = [12.0, 6.0];
+/// Distance that the dashes of animated links move per second.
+const LINK_ANIMATION_SPEED: f64 = 30.0;
 
 mod imp {
     use super::*;
 
-    use std::cell::{Cell, RefCell};
     use std::collections::{HashMap, HashSet};
 
     use adw::gtk::gdk::{self};
@@ -50,6 +68,8 @@ mod imp {
         video: gdk::RGBA,
         midi: gdk::RGBA,
         unknown: gdk::RGBA,
+        /// Color of links that failed.
+        error: gdk::RGBA,
     }
 
     impl Colors {
@@ -63,6 +83,121 @@ mod imp {
         }
     }
 
+    /// A widget a link is visually attached to, and the point the link starts or ends at in canvas-space.
+    type LinkEndpoint = (gtk::Widget, Point);
+
+    /// The anchors of the links on the ports of a node, relative to the node.
+    ///
+    /// Computing anchors is expensive when there are many links, so they are kept
+    /// until the node changes its size or the layout of its ports.
+    #[derive(Default)]
+    pub(super) struct AnchorCache {
+        /// Width, height and layout serial of the node when the anchors were computed.
+        layout: (i32, i32, u32),
+        anchors: HashMap<Port, Point>,
+    }
+
+    /// The route of a link bundle in canvas-space, together with what it was routed from.
+    struct RoutedLink {
+        style: LinkStyle,
+        output_anchor: Point,
+        input_anchor: Point,
+        output_node: Option<graphene::Rect>,
+        input_node: Option<graphene::Rect>,
+        path: LinkPath,
+        bounds: graphene::Rect,
+    }
+
+    impl RoutedLink {
+        fn is_route_of(
+            &self,
+            style: LinkStyle,
+            bundle: &LinkBundle,
+            output_node: Option<&graphene::Rect>,
+            input_node: Option<&graphene::Rect>,
+        ) -> bool {
+            self.style == style
+                && self.output_anchor == bundle.output_anchor
+                && self.input_anchor == bundle.input_anchor
+                && self.output_node.as_ref() == output_node
+                && self.input_node.as_ref() == input_node
+        }
+    }
+
+    /// The routes of link bundles, keyed by the widgets they are attached to.
+    ///
+    /// Routing links around nodes is expensive when there are many of them, so routes are kept
+    /// until their anchors or nodes move, or until a node moves within the columns
+    /// that could change the route, see [`obstacle_columns()`].
+    #[derive(Default)]
+    pub(super) struct RouteCache {
+        /// The areas of all nodes in canvas-space, as of the last frame.
+        node_areas: HashMap<Node, graphene::Rect>,
+        obstacles: Obstacles,
+        links: HashMap<(gtk::Widget, gtk::Widget), RoutedLink>,
+    }
+
+    impl RouteCache {
+        /// Replace the areas of the nodes, and forget the routes that nodes moved, appeared or disappeared next to.
+        fn update_node_areas(&mut self, node_areas: HashMap<Node, graphene::Rect>) {
+            let mut changed: Vec<graphene::Rect> = node_areas
+                .iter()
+                .filter(|(node, area)| self.node_areas.get(*node) != Some(*area))
+                .map(|(_, area)| *area)
+                .collect();
+            changed.extend(
+                self.node_areas
+                    .iter()
+                    .filter(|(node, area)| node_areas.get(*node) != Some(*area))
+                    .map(|(_, area)| *area),
+            );
+            if changed.is_empty() {
+                return;
+            }
+
+            self.links.retain(|_, route| {
+                let Some((min_x, max_x)) =
+                    obstacle_columns(route.style, &route.output_anchor, &route.input_anchor, 1.0)
+                else {
+                    return true;
+                };
+
+                !changed.iter().any(|area| {
+                    f64::from(area.x()) <= max_x && min_x <= f64::from(area.x() + area.width())
+                })
+            });
+            self.obstacles = Obstacles::new(node_areas.values().copied());
+            self.node_areas = node_areas;
+        }
+    }
+
+    /// One or more links that are drawn as a single line because they connect the same endpoints.
+    struct LinkBundle {
+        output_anchor: Point,
+        input_anchor: Point,
+        links: Vec<Link>,
+        /// The state of the link that stands out most, see [`link_state_priority()`].
+        state: LinkState,
+        /// Whether all links of the bundle are passive.
+        passive: bool,
+        media_type: MediaType,
+        /// Whether the bundle contains a link of the hovered port or node.
+        highlighted: bool,
+    }
+
+    /// The nodes, ports and links that signals flow through from a node, see [`super::GraphView::trace_signal_path()`].
+    pub(super) struct SignalTrace {
+        /// The node that the signal path was traced from.
+        pub start: Node,
+        /// The direction that links were followed in.
+        pub direction: Direction,
+        pub nodes: HashSet<Node>,
+        pub ports: HashSet<Port>,
+        pub links: HashSet<Link>,
+        /// Nodes at which the signal path ends, in the order they were reached.
+        pub endpoints: Vec<Node>,
+    }
+
     pub struct DragState {
         node: glib::WeakRef<Node>,
         /// This stores the offset of the pointer to the origin of the node,
@@ -77,12 +212,36 @@ mod imp {
         pub(super) nodes: RefCell<HashMap<Node, Point>>,
         /// Stores the links and whether they are currently active.
         pub(super) links: RefCell<HashSet<Link>>,
+        /// The links attached to each port, so that they can be found without going through all links.
+        pub(super) port_links: RefCell<HashMap<Port, Vec<Link>>>,
+        /// Link anchors of the ports of each node, see [`Self::port_anchor()`].
+        pub(super) anchor_cache: RefCell<HashMap<Node, AnchorCache>>,
+        /// Routes of the links drawn in the last frames, see [`RouteCache`].
+        pub(super) route_cache: RefCell<RouteCache>,
+        /// How many link bundles were routed since the benchmark last reported, see [`super::GraphView::start_benchmark()`].
+        #[cfg(feature = "benchmark")]
+        pub(super) routed_links: Cell<usize>,
+        /// The lines that links were drawn as in the last frame, together with their width,
+        /// so that the links under the pointer can be found.
+        pub(super) link_hit_areas: RefCell<Vec<(LinkPath, f64, Vec<Link>)>>,
+        /// Colors to draw links with, looked up once instead of on every frame.
+        colors: RefCell<Option<Rc<Colors>>>,
 
         // Properties for zooming and scrolling the hraph
         pub hadjustment: RefCell<Option<gtk::Adjustment>>,
         pub vadjustment: RefCell<Option<gtk::Adjustment>>,
         pub zoom_factor: Cell<f64>,
 
+        /// How the lines of links are routed.
+        pub link_style: Cell<LinkStyle>,
+
+        /// Whether the dashes of active links move from their output to their input.
+        pub animate_links: Cell<bool>,
+        /// How far the dashes of animated links have moved.
+        link_animation_offset: Cell<f64>,
+        /// The tick callback moving the dashes of animated links while the graph is shown.
+        link_animation_tick: RefCell<Option<gtk::TickCallbackId>>,
+
         /// This keeps track of an ongoing node drag operation.
         pub dragged_node: RefCell<Option<DragState>>,
 
@@ -90,6 +249,18 @@ mod imp {
         pub dragged_port: glib::WeakRef<Port>,
         pub port_drag_cursor: Cell<Point>,
 
+        /// A port that has been picked up using the keyboard to be linked to another port.
+        pub held_port: glib::WeakRef<Port>,
+
+        /// The port or node under the pointer, whose links are highlighted.
+        pub(super) hovered: glib::WeakRef<gtk::Widget>,
+        /// Links attached to the hovered port or node.
+        pub(super) highlighted_links: RefCell<HashSet<Link>>,
+        /// Ports at the other end of the highlighted links.
+        pub(super) highlighted_ports: RefCell<Vec<Port>>,
+        /// A signal path that is highlighted while everything else is faded out.
+        pub(super) signal_trace: RefCell<Option<SignalTrace>>,
+
         // Memorized data for an in-progress zoom gesture
         pub zoom_gesture_initial_zoom: Cell<Option<f64>>,
         pub zoom_gesture_anchor: Cell<Option<(f64, f64)>>,
@@ -103,12 +274,28 @@ mod imp {
             Self {
                 nodes: Default::default(),
                 links: Default::default(),
+                port_links: Default::default(),
+                anchor_cache: Default::default(),
+                route_cache: Default::default(),
+                #[cfg(feature = "benchmark")]
+                routed_links: Default::default(),
+                link_hit_areas: Default::default(),
+                colors: Default::default(),
                 hadjustment: Default::default(),
                 vadjustment: Default::default(),
                 zoom_factor: Default::default(),
+                link_style: Default::default(),
+                animate_links: Default::default(),
+                link_animation_offset: Default::default(),
+                link_animation_tick: Default::default(),
                 dragged_node: Default::default(),
                 dragged_port: Default::default(),
                 port_drag_cursor: Cell::new(Point::new(0.0, 0.0)),
+                held_port: Default::default(),
+                hovered: Default::default(),
+                highlighted_links: Default::default(),
+                highlighted_ports: Default::default(),
+                signal_trace: Default::default(),
                 zoom_gesture_initial_zoom: Default::default(),
                 zoom_gesture_anchor: Default::default(),
                 move_view_state: Default::default(),
@@ -141,6 +328,26 @@ mod imp {
             self.setup_scroll_zooming();
             self.setup_zoom_gesture();
             self.setup_move_view();
+            self.setup_keyboard_navigation();
+            self.setup_hover_highlighting();
+
+            // Links are drawn by the graph itself, so it provides their tooltips.
+            self.obj().set_has_tooltip(true);
+            self.obj()
+                .connect_query_tooltip(|graph, x, y, _keyboard_mode, tooltip| {
+                    graph
+                        .imp()
+                        .query_link_tooltip(f64::from(x), f64::from(y), tooltip)
+                });
+
+            // The colors may be different for another style, so look them up again on the next frame.
+            let style_manager = adw::StyleManager::default();
+            style_manager.connect_dark_notify(clone!(@weak self as imp => move |_| {
+                imp.colors.replace(None);
+            }));
+            style_manager.connect_high_contrast_notify(clone!(@weak self as imp => move |_| {
+                imp.colors.replace(None);
+            }));
         }
 
         fn dispose(&self) {
@@ -163,18 +370,39 @@ mod imp {
                         .default_value(1.0)
                         .flags(glib::ParamFlags::CONSTRUCT | glib::ParamFlags::READWRITE)
                         .build(),
+                    glib::ParamSpecEnum::builder::<LinkStyle>("link-style")
+                        .explicit_notify()
+                        .build(),
+                    glib::ParamSpecBoolean::builder("animate-links")
+                        .explicit_notify()
+                        .build(),
                 ]
             });
 
             PROPERTIES.as_ref()
         }
 
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![
+                    // Emitted whenever nodes have been moved or the visible area has changed.
+                    Signal::builder("layout-changed").build(),
+                    // Emitted whenever a signal path was traced or the trace was cleared.
+                    Signal::builder("signal-trace-changed").build(),
+                ]
+            });
+
+            SIGNALS.as_ref()
+        }
+
         fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
             match pspec.name() {
                 "hadjustment" => self.hadjustment.borrow().to_value(),
                 "vadjustment" => self.vadjustment.borrow().to_value(),
                 "hscroll-policy" | "vscroll-policy" => gtk::ScrollablePolicy::Natural.to_value(),
                 "zoom-factor" => self.zoom_factor.get().to_value(),
+                "link-style" => self.link_style.get().to_value(),
+                "animate-links" => self.animate_links.get().to_value(),
                 _ => unimplemented!(),
             }
         }
@@ -194,12 +422,37 @@ mod imp {
                     self.zoom_factor.set(value.get().unwrap());
                     obj.queue_allocate();
                 }
+                "link-style" => {
+                    let link_style = value.get().unwrap();
+                    if self.link_style.replace(link_style) != link_style {
+                        obj.queue_draw();
+                        obj.notify("link-style");
+                    }
+                }
+                "animate-links" => {
+                    let animate_links = value.get().unwrap();
+                    if self.animate_links.replace(animate_links) != animate_links {
+                        self.update_link_animation();
+                        obj.queue_draw();
+                        obj.notify("animate-links");
+                    }
+                }
                 _ => unimplemented!(),
             }
         }
     }
 
     impl WidgetImpl for GraphView {
+        fn map(&self) {
+            self.parent_map();
+            self.update_link_animation();
+        }
+
+        fn unmap(&self) {
+            self.parent_unmap();
+            self.update_link_animation();
+        }
+
         fn size_allocate(&self, _width: i32, _height: i32, baseline: i32) {
             let widget = &*self.obj();
 
@@ -224,6 +477,26 @@ mod imp {
             if let Some(ref vadjustment) = *self.vadjustment.borrow() {
                 self.set_adjustment_values(widget, vadjustment, gtk::Orientation::Vertical);
             }
+
+            widget.emit_by_name::<()>("layout-changed", &[]);
+        }
+
+        fn set_focus_child(&self, child: Option<&gtk::Widget>) {
+            self.parent_set_focus_child(child);
+
+            // Scroll to nodes that receive keyboard focus while being outside the view.
+            if let Some(node) = child.and_then(|child| child.downcast_ref::<Node>()) {
+                let widget = &*self.obj();
+                let view =
+                    graphene::Rect::new(0.0, 0.0, widget.width() as f32, widget.height() as f32);
+
+                if node
+                    .compute_bounds(widget)
+                    .is_some_and(|bounds| bounds.intersection(&view).is_none())
+                {
+                    widget.scroll_to_widget(node);
+                }
+            }
         }
 
         fn snapshot(&self, snapshot: &gtk::Snapshot) {
@@ -264,7 +537,7 @@ mod imp {
         /// Returns a [`gsk::Transform`] matrix that can translate from screen space to canvas space.
         ///
         /// This is the inverted form of [`Self::canvas_space_to_screen_space_transform()`], see that function for a more detailed explantion.
-        fn screen_space_to_canvas_space_transform(&self) -> gsk::Transform {
+        pub(super) fn screen_space_to_canvas_space_transform(&self) -> gsk::Transform {
             self.canvas_space_to_screen_space_transform()
                 .invert()
                 .unwrap()
@@ -293,6 +566,12 @@ mod imp {
                     // Drag the Node around the screen.
                     let node = target.dynamic_cast_ref::<Node>().unwrap();
 
+                    // Also select the node, adding it to the current selection if a modifier is held.
+                    let extend_selection = drag_controller.current_event_state().intersects(
+                        gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::SHIFT_MASK,
+                    );
+                    widget.select_node(node, extend_selection);
+
                     let Some(canvas_node_pos) = widget.node_position(node) else {
                         return;
                     };
@@ -309,6 +588,9 @@ mod imp {
                         ),
                     })
                 } else {
+                    // The user clicked on the empty canvas.
+                    widget.clear_selection();
+
                     None
                 }
             });
@@ -512,24 +794,227 @@ mod imp {
             self.obj().add_controller(drag_controller);
         }
 
+        fn setup_hover_highlighting(&self) {
+            let motion_controller = gtk::EventControllerMotion::new();
+
+            motion_controller.connect_motion(|controller, x, y| {
+                let graph = controller
+                    .widget()
+                    .downcast::<super::GraphView>()
+                    .expect("Widget should be a graphview");
+
+                // Ports are checked first, as they are contained by their node.
+                let hovered = graph
+                    .pick(x, y, gtk::PickFlags::DEFAULT)
+                    .and_then(|widget| {
+                        widget
+                            .ancestor(Port::static_type())
+                            .or_else(|| widget.ancestor(Node::static_type()))
+                    });
+
+                let imp = graph.imp();
+                if imp.hovered.upgrade() != hovered {
+                    imp.hovered.set(hovered.as_ref());
+                    imp.update_highlight();
+                }
+            });
+
+            motion_controller.connect_leave(|controller| {
+                let graph = controller
+                    .widget()
+                    .downcast::<super::GraphView>()
+                    .expect("Widget should be a graphview");
+
+                let imp = graph.imp();
+                if imp.hovered.upgrade().is_some() {
+                    imp.hovered.set(None);
+                    imp.update_highlight();
+                }
+            });
+
+            self.obj().add_controller(motion_controller);
+        }
+
+        /// Highlight the links of the hovered port or node and the ports at their other end.
+        pub(super) fn update_highlight(&self) {
+            let ports = match self.hovered.upgrade() {
+                Some(widget) => match widget.downcast::<Port>() {
+                    Ok(port) => vec![port],
+                    Err(widget) => widget
+                        .downcast::<Node>()
+                        .map(|node| node.ports())
+                        .unwrap_or_default(),
+                },
+                None => Vec::new(),
+            };
+
+            let port_links = self.port_links.borrow();
+            let links: HashSet<Link> = ports
+                .iter()
+                .filter_map(|port| port_links.get(port))
+                .flatten()
+                .cloned()
+                .collect();
+
+            let peers: Vec<Port> = links
+                .iter()
+                .flat_map(|link| [link.output_port(), link.input_port()])
+                .flatten()
+                .filter(|peer| !ports.contains(peer))
+                .collect();
+
+            for port in self.highlighted_ports.replace(peers).iter() {
+                port.remove_css_class("highlighted");
+            }
+            for port in self.highlighted_ports.borrow().iter() {
+                port.add_css_class("highlighted");
+            }
+
+            self.highlighted_links.replace(links);
+            self.obj().queue_draw();
+        }
+
+        fn setup_keyboard_navigation(&self) {
+            let key_controller = gtk::EventControllerKey::new();
+
+            key_controller.connect_key_pressed(|controller, key, _, modifiers| {
+                let widget = controller
+                    .widget()
+                    .downcast::<super::GraphView>()
+                    .expect("Widget should be a graphview");
+
+                widget.imp().handle_key_press(key, modifiers)
+            });
+
+            self.obj().add_controller(key_controller);
+        }
+
+        /// Handle a key press that was not handled by the focused node or port.
+        ///
+        /// Arrow keys move the focused node, while space or enter pick up the focused port
+        /// so that it can be linked to the next port this is pressed on.
+        fn handle_key_press(
+            &self,
+            key: gdk::Key,
+            modifiers: gdk::ModifierType,
+        ) -> glib::Propagation {
+            let widget = &*self.obj();
+            let Some(focus) = widget.root().and_then(|root| root.focus()) else {
+                return glib::Propagation::Proceed;
+            };
+
+            match key {
+                gdk::Key::Escape if self.held_port.upgrade().is_some() => {
+                    self.drop_held_port();
+                    glib::Propagation::Stop
+                }
+                gdk::Key::Escape if self.signal_trace.borrow().is_some() => {
+                    widget.clear_signal_trace();
+                    glib::Propagation::Stop
+                }
+                gdk::Key::space | gdk::Key::Return | gdk::Key::KP_Enter => {
+                    let Some(port) = focus.downcast_ref::<Port>() else {
+                        return glib::Propagation::Proceed;
+                    };
+
+                    self.toggle_held_port(port, modifiers);
+                    glib::Propagation::Stop
+                }
+                gdk::Key::Left | gdk::Key::Right | gdk::Key::Up | gdk::Key::Down => {
+                    let Some(node) = focus.downcast_ref::<Node>() else {
+                        return glib::Propagation::Proceed;
+                    };
+                    let Some(position) = widget.node_position(node) else {
+                        return glib::Propagation::Proceed;
+                    };
+
+                    let step = if modifiers.contains(gdk::ModifierType::SHIFT_MASK) {
+                        50.0
+                    } else {
+                        10.0
+                    };
+                    let (delta_x, delta_y) = match key {
+                        gdk::Key::Left => (-step, 0.0),
+                        gdk::Key::Right => (step, 0.0),
+                        gdk::Key::Up => (0.0, -step),
+                        _ => (0.0, step),
+                    };
+
+                    widget.move_node(
+                        node,
+                        &Point::new(position.x() + delta_x, position.y() + delta_y),
+                    );
+                    glib::Propagation::Stop
+                }
+                _ => glib::Propagation::Proceed,
+            }
+        }
+
+        /// Pick up the specified port if no port is held yet, otherwise toggle a link between the held port
+        /// and the specified port, with options depending on the held modifier keys.
+        fn toggle_held_port(&self, port: &Port, modifiers: gdk::ModifierType) {
+            let Some(held_port) = self.held_port.upgrade() else {
+                port.add_css_class("held");
+                self.held_port.set(Some(port));
+                self.obj().queue_draw();
+                return;
+            };
+
+            if &held_port != port {
+                if !held_port.is_linkable_to(port) {
+                    self.obj().error_bell();
+                    return;
+                }
+
+                let (output_port, input_port) = match Direction::from_raw(port.direction()) {
+                    Direction::Output => (port, &held_port),
+                    Direction::Input => (&held_port, port),
+                    _ => unreachable!(),
+                };
+                let options = link_options_for_modifiers(modifiers);
+
+                port.emit_by_name::<()>(
+                    "port-toggled",
+                    &[
+                        &output_port.pipewire_id(),
+                        &input_port.pipewire_id(),
+                        &options.linger,
+                        &options.passive,
+                    ],
+                );
+            }
+
+            self.drop_held_port();
+        }
+
+        fn drop_held_port(&self) {
+            if let Some(held_port) = self.held_port.upgrade() {
+                held_port.remove_css_class("held");
+            }
+            self.held_port.set(None);
+            self.obj().queue_draw();
+        }
+
         fn draw_link(
             &self,
             link_cr: &cairo::Context,
-            output_anchor: &Point,
-            input_anchor: &Point,
-            active: bool,
+            path: &LinkPath,
+            state: LinkState,
             color: &gdk::RGBA,
         ) {
-            let output_x: f64 = output_anchor.x().into();
-            let output_y: f64 = output_anchor.y().into();
-            let input_x: f64 = input_anchor.x().into();
-            let input_y: f64 = input_anchor.y().into();
-
-            // Use dashed line for inactive links, full line otherwise.
-            if active {
-                link_cr.set_dash(&[], 0.0);
-            } else {
-                link_cr.set_dash(&[10.0, 5.0], 0.0);
+            match state {
+                LinkState::Active if self.animate_links.get() => {
+                    // A decreasing offset moves the dashes towards the end of the path.
+                    link_cr.set_dash(&LINK_ANIMATION_DASHES, -self.link_animation_offset.get());
+                }
+                LinkState::Active => link_cr.set_dash(&[], 0.0),
+                LinkState::Paused => link_cr.set_dash(&[10.0, 5.0], 0.0),
+                // Links that are still being set up are drawn with increasingly long dashes,
+                // the closer they are to being ready.
+                LinkState::Allocating => link_cr.set_dash(&[8.0, 3.0, 2.0, 3.0], 0.0),
+                LinkState::Negotiating => link_cr.set_dash(&[2.0, 4.0], 0.0),
+                LinkState::Init | LinkState::Unlinked => link_cr.set_dash(&[2.0, 8.0], 0.0),
+                LinkState::Error => link_cr.set_dash(&[5.0, 3.0], 0.0),
             }
 
             link_cr.set_source_rgba(
@@ -539,34 +1024,53 @@ mod imp {
                 color.alpha().into(),
             );
 
-            // If the output port is farther right than the input port and they have
-            // a similar y coordinate, apply a y offset to the control points
-            // so that the curve sticks out a bit.
-            let y_control_offset = if output_x > input_x {
-                f64::max(0.0, 25.0 - (output_y - input_y).abs())
-            } else {
-                0.0
-            };
-
-            // Place curve control offset by half the x distance between the two points.
-            // This makes the curve scale well for varying distances between the two ports,
-            // especially when the output port is farther right than the input port.
-            let half_x_dist = f64::abs(output_x - input_x) / 2.0;
-            link_cr.move_to(output_x, output_y);
-            link_cr.curve_to(
-                output_x + half_x_dist,
-                output_y - y_control_offset,
-                input_x - half_x_dist,
-                input_y - y_control_offset,
-                input_x,
-                input_y,
-            );
+            path.append_to(link_cr);
 
             if let Err(e) = link_cr.stroke() {
                 warn!("Failed to draw graphview links: {}", e);
             };
         }
 
+        /// Draw a link from the port that was picked up using the keyboard to the currently focused port,
+        /// if the two can be linked.
+        fn draw_held_link(&self, port: &Port, link_cr: &cairo::Context, colors: &Colors) {
+            let Some(focused_port) = self
+                .obj()
+                .root()
+                .and_then(|root| root.focus())
+                .and_downcast::<Port>()
+                .filter(|focused_port| port.is_linkable_to(focused_port))
+            else {
+                return;
+            };
+
+            let (Some(port_anchor), Some(focused_anchor)) = (
+                port.compute_point(&*self.obj(), &port.link_anchor()),
+                focused_port.compute_point(&*self.obj(), &focused_port.link_anchor()),
+            ) else {
+                return;
+            };
+
+            let (output_anchor, input_anchor) = match Direction::from_raw(port.direction()) {
+                Direction::Output => (&port_anchor, &focused_anchor),
+                Direction::Input => (&focused_anchor, &port_anchor),
+                _ => unreachable!(),
+            };
+
+            let color = &colors.color_for_media_type(MediaType::from_raw(port.media_type()));
+
+            let path = route_link(
+                self.link_style.get(),
+                output_anchor,
+                input_anchor,
+                None,
+                None,
+                &Obstacles::default(),
+                self.zoom_factor.get(),
+            );
+            self.draw_link(link_cr, &path, LinkState::Init, color);
+        }
+
         fn draw_dragged_link(&self, port: &Port, link_cr: &cairo::Context, colors: &Colors) {
             let Some(port_anchor) = port.compute_point(&*self.obj(), &port.link_anchor()) else {
                 return;
@@ -597,7 +1101,16 @@ mod imp {
 
             let color = &colors.color_for_media_type(MediaType::from_raw(port.media_type()));
 
-            self.draw_link(link_cr, output_anchor, input_anchor, false, color);
+            let path = route_link(
+                self.link_style.get(),
+                output_anchor,
+                input_anchor,
+                None,
+                None,
+                &Obstacles::default(),
+                self.zoom_factor.get(),
+            );
+            self.draw_link(link_cr, &path, LinkState::Init, color);
         }
 
         fn snapshot_links(&self, widget: &super::GraphView, snapshot: &gtk::Snapshot) {
@@ -612,65 +1125,419 @@ mod imp {
 
             link_cr.set_line_width(2.0 * self.zoom_factor.get());
 
-            let colors = Colors {
-                audio: widget
-                    .style_context()
-                    .lookup_color("media-type-audio")
-                    .expect("color not found"),
-                video: widget
-                    .style_context()
-                    .lookup_color("media-type-video")
-                    .expect("color not found"),
-                midi: widget
-                    .style_context()
-                    .lookup_color("media-type-midi")
-                    .expect("color not found"),
-                unknown: widget
-                    .style_context()
-                    .lookup_color("media-type-unknown")
-                    .expect("color not found"),
+            let colors = self.colors();
+
+            // Links whose ports are hidden inside a collapsed node are attached to the node instead,
+            // so multiple links may connect the same two endpoints.
+            // Group them so that they can be drawn as a single bundle.
+            let mut bundles: HashMap<(gtk::Widget, gtk::Widget), LinkBundle> = HashMap::new();
+            let highlighted_links = self.highlighted_links.borrow();
+            let signal_trace = self.signal_trace.borrow();
+            let is_highlighted = |link: &Link| {
+                highlighted_links.contains(link)
+                    || signal_trace
+                        .as_ref()
+                        .is_some_and(|trace| trace.links.contains(link))
             };
 
             for link in self.links.borrow().iter() {
-                let color = &colors.color_for_media_type(link.media_type());
-
-                // TODO: Do not draw links when they are outside the view
-                let Some((output_anchor, input_anchor)) = self.get_link_coordinates(link) else {
+                let Some((output, input)) = self.get_link_coordinates(link) else {
                     warn!("Could not get allocation of ports of link: {:?}", link);
                     continue;
                 };
 
-                self.draw_link(
-                    &link_cr,
-                    &output_anchor,
-                    &input_anchor,
-                    link.active(),
-                    color,
-                );
+                bundles
+                    .entry((output.0, input.0))
+                    .and_modify(|bundle| {
+                        bundle.links.push(link.clone());
+                        bundle.passive &= link.passive();
+                        if link_state_priority(link.state()) > link_state_priority(bundle.state) {
+                            bundle.state = link.state();
+                        }
+                        bundle.highlighted |= is_highlighted(link);
+                    })
+                    .or_insert(LinkBundle {
+                        output_anchor: output.1,
+                        input_anchor: input.1,
+                        links: vec![link.clone()],
+                        state: link.state(),
+                        passive: link.passive(),
+                        media_type: link.media_type(),
+                        highlighted: is_highlighted(link),
+                    });
+            }
+
+            let zoom_factor = self.zoom_factor.get();
+            let line_width = 2.0 * zoom_factor;
+
+            // Links outside of the view are not drawn, and not routed either if they aren't routed yet.
+            let view = widget
+                .visible_rect()
+                .inset_r(-LINK_CULL_MARGIN, -LINK_CULL_MARGIN);
+            let (hadj, vadj) = (
+                self.hadjustment.borrow().as_ref().unwrap().value(),
+                self.vadjustment.borrow().as_ref().unwrap().value(),
+            );
+
+            // Links are routed around the areas of nodes, in canvas-space so that routes stay valid while zooming.
+            let link_style = self.link_style.get();
+            let node_areas: HashMap<Node, graphene::Rect> = self
+                .nodes
+                .borrow()
+                .iter()
+                .map(|(node, position)| {
+                    let area = graphene::Rect::new(
+                        position.x(),
+                        position.y(),
+                        node.width() as f32,
+                        node.height() as f32,
+                    );
+                    (node.clone(), area)
+                })
+                .collect();
+            let mut route_cache = self.route_cache.borrow_mut();
+            let route_cache = &mut *route_cache;
+            route_cache.update_node_areas(node_areas);
+            route_cache
+                .links
+                .retain(|endpoints, _| bundles.contains_key(endpoints));
+
+            // While links are highlighted, the others are dimmed and the highlighted ones are drawn on top of them.
+            let highlighting = !highlighted_links.is_empty() || signal_trace.is_some();
+            let mut bundles: Vec<_> = bundles.into_iter().collect();
+            if highlighting {
+                bundles.sort_by_key(|(_, bundle)| bundle.highlighted);
             }
 
+            let mut link_hit_areas = Vec::new();
+
+            for (endpoints, bundle) in bundles {
+                let node_area = |widget: &gtk::Widget| {
+                    widget
+                        .ancestor(Node::static_type())
+                        .and_downcast::<Node>()
+                        .and_then(|node| route_cache.node_areas.get(&node))
+                };
+                let (output_node, input_node) = (node_area(&endpoints.0), node_area(&endpoints.1));
+
+                let cached = route_cache.links.get(&endpoints).filter(|route| {
+                    route.is_route_of(link_style, &bundle, output_node, input_node)
+                });
+                let route = match cached {
+                    Some(route) => route,
+                    None => {
+                        let area = route_area(
+                            link_style,
+                            &bundle.output_anchor,
+                            &bundle.input_anchor,
+                            1.0,
+                        );
+                        if area.intersection(&view).is_none() {
+                            continue;
+                        }
+
+                        let path = route_link(
+                            link_style,
+                            &bundle.output_anchor,
+                            &bundle.input_anchor,
+                            output_node,
+                            input_node,
+                            &route_cache.obstacles,
+                            1.0,
+                        );
+                        let route = RoutedLink {
+                            style: link_style,
+                            output_anchor: bundle.output_anchor,
+                            input_anchor: bundle.input_anchor,
+                            output_node: output_node.copied(),
+                            input_node: input_node.copied(),
+                            bounds: path.bounds(),
+                            path,
+                        };
+                        route_cache.links.insert(endpoints.clone(), route);
+                        #[cfg(feature = "benchmark")]
+                        self.routed_links.set(self.routed_links.get() + 1);
+                        &route_cache.links[&endpoints]
+                    }
+                };
+
+                if route.bounds.intersection(&view).is_none() {
+                    continue;
+                }
+                let path = route.path.transformed(zoom_factor, (-hadj, -vadj));
+
+                let color = if bundle.state == LinkState::Error {
+                    &colors.error
+                } else {
+                    colors.color_for_media_type(bundle.media_type)
+                };
+                let color = &if !highlighting {
+                    *color
+                } else if bundle.highlighted {
+                    // Brighten the color by mixing it with white.
+                    gdk::RGBA::new(
+                        color.red() + (1.0 - color.red()) * 0.4,
+                        color.green() + (1.0 - color.green()) * 0.4,
+                        color.blue() + (1.0 - color.blue()) * 0.4,
+                        color.alpha(),
+                    )
+                } else {
+                    color.with_alpha(color.alpha() * 0.3)
+                };
+
+                // Draw bundles thicker the more links they contain, up to a limit,
+                // and passive links thinner, as they do not keep their nodes running.
+                let count = bundle.links.len() as u32;
+                let mut width = line_width * f64::from(count.min(4));
+                if bundle.passive {
+                    width /= 2.0;
+                }
+                if bundle.highlighted {
+                    width *= 1.5;
+                }
+                link_cr.set_line_width(width);
+
+                self.draw_link(&link_cr, &path, bundle.state, color);
+
+                if count > 1 {
+                    self.draw_bundle_badge(&link_cr, path.middle(), count, color);
+                }
+
+                link_hit_areas.push((path, width, bundle.links));
+            }
+
+            self.link_hit_areas.replace(link_hit_areas);
+
+            link_cr.set_line_width(line_width);
+
             if let Some(port) = self.dragged_port.upgrade() {
                 self.draw_dragged_link(&port, &link_cr, &colors);
             }
+
+            if let Some(port) = self.held_port.upgrade() {
+                self.draw_held_link(&port, &link_cr, &colors);
+            }
+        }
+
+        /// Show the state of the links under the pointer in a tooltip, including why they failed.
+        fn query_link_tooltip(&self, x: f64, y: f64, tooltip: &gtk::Tooltip) -> bool {
+            let Ok(surface) = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1) else {
+                return false;
+            };
+            let Ok(hit_cr) = cairo::Context::new(&surface) else {
+                return false;
+            };
+
+            // Links drawn last are on top, so they are checked first.
+            let link_hit_areas = self.link_hit_areas.borrow();
+            let Some((_, _, links)) = link_hit_areas.iter().rev().find(|(path, width, _)| {
+                hit_cr.new_path();
+                path.append_to(&hit_cr);
+                // Make thin lines easier to hit.
+                hit_cr.set_line_width(width + 6.0);
+                hit_cr.in_stroke(x, y).unwrap_or(false)
+            }) else {
+                return false;
+            };
+
+            let text = links
+                .iter()
+                .map(|link| {
+                    let port_name = |port: Option<Port>| {
+                        port.map(|port| port.display_name()).unwrap_or_default()
+                    };
+                    format!(
+                        "{} → {}: {}",
+                        port_name(link.output_port()),
+                        port_name(link.input_port()),
+                        link_description(link)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            tooltip.set_text(Some(&text));
+            true
+        }
+
+        /// Start or stop moving the dashes of active links,
+        /// so that they are only animated while enabled and while the graph is shown.
+        fn update_link_animation(&self) {
+            let widget = self.obj();
+            let animate = self.animate_links.get() && widget.is_mapped();
+
+            if !animate {
+                if let Some(tick) = self.link_animation_tick.take() {
+                    tick.remove();
+                }
+                return;
+            }
+            if self.link_animation_tick.borrow().is_some() {
+                return;
+            }
+
+            let tick = widget.add_tick_callback(|graph, frame_clock| {
+                let imp = graph.imp();
+
+                // Frame times are in microseconds.
+                let seconds = frame_clock.frame_time() as f64 / 1_000_000.0;
+                let period: f64 = LINK_ANIMATION_DASHES.iter().sum();
+                imp.link_animation_offset
+                    .set((seconds * LINK_ANIMATION_SPEED) % period);
+
+                if imp
+                    .links
+                    .borrow()
+                    .iter()
+                    .any(|link| link.state() == LinkState::Active)
+                {
+                    graph.queue_draw();
+                }
+
+                glib::ControlFlow::Continue
+            });
+            self.link_animation_tick.replace(Some(tick));
         }
 
-        /// Get coordinates for the drawn link to start at and to end at.
+        /// Get the colors to draw links with, looking them up from the style if needed.
+        fn colors(&self) -> Rc<Colors> {
+            if let Some(colors) = self.colors.borrow().as_ref() {
+                return colors.clone();
+            }
+
+            let widget = self.obj();
+            let lookup = |name| {
+                widget
+                    .style_context()
+                    .lookup_color(name)
+                    .expect("color not found")
+            };
+            let colors = Rc::new(Colors {
+                audio: lookup("media-type-audio"),
+                video: lookup("media-type-video"),
+                midi: lookup("media-type-midi"),
+                unknown: lookup("media-type-unknown"),
+                error: lookup("error_color"),
+            });
+
+            self.colors.replace(Some(colors.clone()));
+            colors
+        }
+
+        /// Draw a badge showing the number of links in a bundle at the specified center.
+        fn draw_bundle_badge(
+            &self,
+            link_cr: &cairo::Context,
+            (center_x, center_y): (f64, f64),
+            count: u32,
+            color: &gdk::RGBA,
+        ) {
+            let zoom_factor = self.zoom_factor.get();
+            let text = count.to_string();
+
+            link_cr.set_dash(&[], 0.0);
+            link_cr.set_font_size(11.0 * zoom_factor);
+            let Ok(extents) = link_cr.text_extents(&text) else {
+                return;
+            };
+            let radius = f64::max(extents.width(), extents.height()) / 2.0 + 4.0 * zoom_factor;
+
+            link_cr.new_sub_path();
+            link_cr.arc(center_x, center_y, radius, 0.0, 2.0 * std::f64::consts::PI);
+            link_cr.set_source_rgba(
+                color.red().into(),
+                color.green().into(),
+                color.blue().into(),
+                color.alpha().into(),
+            );
+            if let Err(e) = link_cr.fill() {
+                warn!("Failed to draw link bundle badge: {}", e);
+                return;
+            }
+
+            link_cr.set_source_rgb(0.0, 0.0, 0.0);
+            link_cr.move_to(
+                center_x - extents.width() / 2.0 - extents.x_bearing(),
+                center_y - extents.height() / 2.0 - extents.y_bearing(),
+            );
+            if let Err(e) = link_cr.show_text(&text) {
+                warn!("Failed to draw link bundle badge: {}", e);
+            }
+        }
+
+        /// Get coordinates for the drawn link to start at and to end at,
+        /// together with the widgets the link is visually attached to.
+        ///
+        /// See [`Self::link_endpoint()`] for how the endpoints are determined.
         ///
         /// # Returns
-        /// `Some((output_anchor, input_anchor))` if all objects the links refers to exist as widgets
-        /// and those widgets are contained by the graph.
+        /// `Some(((output_widget, output_anchor), (input_widget, input_anchor)))` if all objects the links refers to
+        /// exist as widgets and those widgets are contained by the graph.
         ///
-        /// The returned coordinates are in screen-space of the graph.
-        fn get_link_coordinates(&self, link: &Link) -> Option<(graphene::Point, graphene::Point)> {
-            let widget = &*self.obj();
+        /// The returned coordinates are in canvas-space.
+        pub(super) fn get_link_coordinates(
+            &self,
+            link: &Link,
+        ) -> Option<(LinkEndpoint, LinkEndpoint)> {
+            let output = self.link_endpoint(&link.output_port()?)?;
+            let input = self.link_endpoint(&link.input_port()?)?;
+
+            Some((output, input))
+        }
+
+        /// Get the widget that a link attached to the specified port should be drawn to,
+        /// together with the coordinates the link should start or end at.
+        ///
+        /// This is usually the port itself, but if the port is hidden inside a collapsed node,
+        /// the link is attached to the edge of the node instead.
+        ///
+        /// # Returns
+        /// `Some((widget, anchor))` if the port exists as a widget that is contained by the graph.
+        ///
+        /// The returned coordinates are in canvas-space.
+        fn link_endpoint(&self, port: &Port) -> Option<LinkEndpoint> {
+            let node = port.ancestor(Node::static_type()).and_downcast::<Node>()?;
+            let node_position = self.obj().node_position(&node)?;
 
-            let output_port = link.output_port()?;
-            let output_anchor = output_port.compute_point(widget, &output_port.link_anchor())?;
+            let (endpoint, anchor): (gtk::Widget, Point) = if node.collapsed() {
+                let direction = Direction::from_raw(port.direction());
+                let anchor = node.collapsed_link_anchor(direction);
 
-            let input_port = link.input_port()?;
-            let input_anchor = input_port.compute_point(widget, &input_port.link_anchor())?;
+                (node.upcast(), anchor)
+            } else {
+                (port.clone().upcast(), self.port_anchor(&node, port)?)
+            };
 
-            Some((output_anchor, input_anchor))
+            // Nodes are laid out in canvas-space, so their coordinates only differ from it by the node position.
+            let anchor = Point::new(
+                node_position.x() + anchor.x(),
+                node_position.y() + anchor.y(),
+            );
+
+            Some((endpoint, anchor))
+        }
+
+        /// Get the point links attached to the port start or end at, relative to the node of the port.
+        ///
+        /// The point is cached, so that moving the node does not require computing it again.
+        fn port_anchor(&self, node: &Node, port: &Port) -> Option<Point> {
+            let layout = (node.width(), node.height(), node.layout_serial());
+
+            let mut anchor_cache = self.anchor_cache.borrow_mut();
+            let cache = anchor_cache.entry(node.clone()).or_default();
+            if cache.layout != layout {
+                cache.layout = layout;
+                cache.anchors.clear();
+            }
+
+            if let Some(anchor) = cache.anchors.get(port) {
+                return Some(*anchor);
+            }
+
+            let anchor = port.compute_point(node, &port.link_anchor())?;
+            cache.anchors.insert(port.clone(), anchor);
+
+            Some(anchor)
         }
 
         fn set_adjustment(
@@ -693,31 +1560,70 @@ mod imp {
             }
         }
 
-        fn set_adjustment_values(
+        pub(super) fn set_adjustment_values(
             &self,
             obj: &super::GraphView,
             adjustment: &gtk::Adjustment,
             orientation: gtk::Orientation,
         ) {
-            let size = match orientation {
-                gtk::Orientation::Horizontal => obj.width(),
-                gtk::Orientation::Vertical => obj.height(),
+            let bounds = obj.canvas_bounds();
+            let (size, lower, upper) = match orientation {
+                gtk::Orientation::Horizontal => {
+                    (obj.width(), bounds.x(), bounds.x() + bounds.width())
+                }
+                gtk::Orientation::Vertical => {
+                    (obj.height(), bounds.y(), bounds.y() + bounds.height())
+                }
                 _ => unimplemented!(),
             };
             let zoom_factor = self.zoom_factor.get();
 
             adjustment.configure(
                 adjustment.value(),
-                -(CANVAS_SIZE / 2.0) * zoom_factor,
-                (CANVAS_SIZE / 2.0) * zoom_factor,
-                (f64::from(size) * 0.1) * zoom_factor,
-                (f64::from(size) * 0.9) * zoom_factor,
-                f64::from(size) * zoom_factor,
+                f64::from(lower) * zoom_factor,
+                f64::from(upper) * zoom_factor,
+                f64::from(size) * 0.1,
+                f64::from(size) * 0.9,
+                f64::from(size),
             );
         }
     }
 }
 
+/// Describe the state of a link and who created it for its tooltip.
+fn link_description(link: &Link) -> String {
+    let state = match link.state() {
+        LinkState::Error => format!(
+            "Failed: {}",
+            link.error().unwrap_or_else(|| "Unknown error".to_string())
+        ),
+        LinkState::Unlinked => "Unlinked".to_string(),
+        LinkState::Init => "Initializing".to_string(),
+        LinkState::Negotiating => "Negotiating format".to_string(),
+        LinkState::Allocating => "Allocating buffers".to_string(),
+        LinkState::Paused => "Paused".to_string(),
+        LinkState::Active => "Active".to_string(),
+    };
+    let passive = if link.passive() { ", passive" } else { "" };
+    let owner = match (link.created_by_helvum(), link.linger()) {
+        (true, true) => "created by Helvum",
+        (true, false) => "created by Helvum, removed when Helvum closes",
+        (false, _) => "created by another application",
+    };
+
+    format!("{state}{passive} ({owner})")
+}
+
+/// Get how much a link state should stand out when links in different states are drawn as one bundle.
+fn link_state_priority(state: LinkState) -> u8 {
+    match state {
+        LinkState::Error => 3,
+        LinkState::Negotiating | LinkState::Allocating => 2,
+        LinkState::Active => 1,
+        _ => 0,
+    }
+}
+
 glib::wrapper! {
     pub struct GraphView(ObjectSubclass<imp::GraphView>)
         @extends gtk::Widget;
@@ -731,6 +1637,26 @@ impl GraphView {
         glib::Object::new()
     }
 
+    /// Get how the lines of links are routed.
+    pub fn link_style(&self) -> LinkStyle {
+        self.property("link-style")
+    }
+
+    /// Set how the lines of links are routed.
+    pub fn set_link_style(&self, link_style: LinkStyle) {
+        self.set_property("link-style", link_style);
+    }
+
+    /// Get whether the dashes of active links move from their output to their input.
+    pub fn animate_links(&self) -> bool {
+        self.property("animate-links")
+    }
+
+    /// Set whether the dashes of active links move from their output to their input.
+    pub fn set_animate_links(&self, animate_links: bool) {
+        self.set_property("animate-links", animate_links);
+    }
+
     pub fn zoom_factor(&self) -> f64 {
         self.property("zoom-factor")
     }
@@ -755,9 +1681,10 @@ impl GraphView {
             )
         });
 
-        let old_zoom = self.imp().zoom_factor.get();
-        let hadjustment_ref = self.imp().hadjustment.borrow();
-        let vadjustment_ref = self.imp().vadjustment.borrow();
+        let imp = self.imp();
+        let old_zoom = imp.zoom_factor.get();
+        let hadjustment_ref = imp.hadjustment.borrow();
+        let vadjustment_ref = imp.vadjustment.borrow();
         let hadjustment = hadjustment_ref.as_ref().unwrap();
         let vadjustment = vadjustment_ref.as_ref().unwrap();
 
@@ -767,10 +1694,15 @@ impl GraphView {
         let new_hadjustment = x_total * zoom_factor - anchor_x_screen;
         let new_vadjustment = y_total * zoom_factor - anchor_y_screen;
 
+        self.set_property("zoom-factor", zoom_factor);
+
+        // The range of the adjustments depends on the zoom level, so update it before setting the new values,
+        // as they would be clamped to the old range otherwise.
+        imp.set_adjustment_values(self, hadjustment, gtk::Orientation::Horizontal);
+        imp.set_adjustment_values(self, vadjustment, gtk::Orientation::Vertical);
+
         hadjustment.set_value(new_hadjustment);
         vadjustment.set_value(new_vadjustment);
-
-        self.set_property("zoom-factor", zoom_factor);
     }
 
     pub fn add_node(&self, node: Node, node_type: Option<NodeType>) {
@@ -806,22 +1738,175 @@ impl GraphView {
             })
             .map_or(20_f32, |(_x, y)| y + 120.0);
 
+        // New nodes are not part of a signal path that is currently traced.
+        if imp.signal_trace.borrow().is_some() {
+            node.add_css_class("faded");
+        }
+
         imp.nodes.borrow_mut().insert(node, Point::new(x, y));
     }
 
     pub fn remove_node(&self, node: &Node) {
-        let mut nodes = self.imp().nodes.borrow_mut();
+        let imp = self.imp();
+        let mut nodes = imp.nodes.borrow_mut();
 
         if nodes.remove(node).is_some() {
+            imp.anchor_cache.borrow_mut().remove(node);
             node.unparent();
         } else {
             log::warn!("Tried to remove non-existant node widget from graph");
         }
+        drop(nodes);
+
+        let is_traced = imp
+            .signal_trace
+            .borrow()
+            .as_ref()
+            .is_some_and(|trace| trace.nodes.contains(node));
+        if is_traced {
+            self.retrace_signal_path();
+        }
+    }
+
+    /// Highlight every node, port and link that signals flow through from the specified node and fade out the rest.
+    ///
+    /// Links are followed from output to input ports for [`Direction::Output`], showing where the signal of the node ends up,
+    /// and in the opposite direction for [`Direction::Input`], showing where its signal comes from.
+    pub fn trace_signal_path(&self, start: &Node, direction: Direction) {
+        self.clear_signal_trace();
+
+        let imp = self.imp();
+        let nodes = imp.nodes.borrow();
+        let port_links = imp.port_links.borrow();
+
+        let mut trace = imp::SignalTrace {
+            start: start.clone(),
+            direction,
+            nodes: HashSet::from([start.clone()]),
+            ports: HashSet::new(),
+            links: HashSet::new(),
+            endpoints: Vec::new(),
+        };
+        let mut queue = VecDeque::from([start.clone()]);
+
+        while let Some(node) = queue.pop_front() {
+            let mut leads_on = false;
+
+            for port in node
+                .ports()
+                .into_iter()
+                .filter(|port| Direction::from_raw(port.direction()) == direction)
+            {
+                for link in port_links.get(&port).into_iter().flatten() {
+                    let peer = match direction {
+                        Direction::Output => link.input_port(),
+                        _ => link.output_port(),
+                    };
+                    let Some(peer) = peer else {
+                        continue;
+                    };
+                    // Links of a node that is being removed may still lead to it.
+                    let Some(peer_node) = peer
+                        .ancestor(Node::static_type())
+                        .and_downcast::<Node>()
+                        .filter(|peer_node| nodes.contains_key(peer_node))
+                    else {
+                        continue;
+                    };
+
+                    leads_on = true;
+                    trace.links.insert(link.clone());
+                    trace.ports.insert(port.clone());
+                    trace.ports.insert(peer);
+                    if trace.nodes.insert(peer_node.clone()) {
+                        queue.push_back(peer_node);
+                    }
+                }
+            }
+
+            if !leads_on && node != *start {
+                trace.endpoints.push(node);
+            }
+        }
+
+        for node in nodes.keys() {
+            if trace.nodes.contains(node) {
+                node.add_css_class("traced");
+            } else {
+                node.add_css_class("faded");
+            }
+        }
+        for port in trace.ports.iter() {
+            port.add_css_class("traced");
+        }
+        // Handlers of the signal may change the graph.
+        drop(nodes);
+        drop(port_links);
+
+        imp.signal_trace.replace(Some(trace));
+        self.emit_by_name::<()>("signal-trace-changed", &[]);
+        self.queue_draw();
+    }
+
+    /// Trace the current signal path again after nodes or links were removed from it,
+    /// so that it does not refer to the removed widgets anymore.
+    ///
+    /// The trace is cleared if the node it was started from was removed.
+    fn retrace_signal_path(&self) {
+        let imp = self.imp();
+        let Some((start, direction)) = imp
+            .signal_trace
+            .borrow()
+            .as_ref()
+            .map(|trace| (trace.start.clone(), trace.direction))
+        else {
+            return;
+        };
+
+        if imp.nodes.borrow().contains_key(&start) {
+            self.trace_signal_path(&start, direction);
+        } else {
+            self.clear_signal_trace();
+        }
+    }
+
+    /// Stop highlighting the signal path traced by [`Self::trace_signal_path()`].
+    pub fn clear_signal_trace(&self) {
+        let imp = self.imp();
+        let Some(trace) = imp.signal_trace.take() else {
+            return;
+        };
+
+        for node in imp.nodes.borrow().keys() {
+            node.remove_css_class("traced");
+            node.remove_css_class("faded");
+        }
+        for port in trace.ports.iter() {
+            port.remove_css_class("traced");
+        }
+
+        self.emit_by_name::<()>("signal-trace-changed", &[]);
+        self.queue_draw();
+    }
+
+    /// Get the nodes at which the currently traced signal path ends, or `None` if no signal path is traced.
+    pub fn signal_trace_endpoints(&self) -> Option<Vec<Node>> {
+        self.imp()
+            .signal_trace
+            .borrow()
+            .as_ref()
+            .map(|trace| trace.endpoints.clone())
     }
 
     pub fn add_link(&self, link: Link) {
         link.connect_notify_local(
-            Some("active"),
+            Some("state"),
+            glib::clone!(@weak self as graph => move |_, _| {
+                graph.queue_draw();
+            }),
+        );
+        link.connect_notify_local(
+            Some("passive"),
             glib::clone!(@weak self as graph => move |_, _| {
                 graph.queue_draw();
             }),
@@ -832,20 +1917,126 @@ impl GraphView {
                 graph.queue_draw();
             }),
         );
-        self.imp().links.borrow_mut().insert(link);
-        self.queue_draw();
+        let imp = self.imp();
+        imp.links.borrow_mut().insert(link.clone());
+        {
+            let mut port_links = imp.port_links.borrow_mut();
+            for port in [link.output_port(), link.input_port()]
+                .into_iter()
+                .flatten()
+            {
+                port_links.entry(port).or_default().push(link.clone());
+            }
+        }
+        self.update_accessible_descriptions(&link);
+        imp.update_highlight();
     }
 
     pub fn remove_link(&self, link: &Link) {
-        let mut links = self.imp().links.borrow_mut();
-        links.remove(link);
+        let imp = self.imp();
+        imp.links.borrow_mut().remove(link);
+        {
+            let mut port_links = imp.port_links.borrow_mut();
+            for port in [link.output_port(), link.input_port()]
+                .into_iter()
+                .flatten()
+            {
+                if let Some(links) = port_links.get_mut(&port) {
+                    links.retain(|other| other != link);
+                    if links.is_empty() {
+                        port_links.remove(&port);
+                    }
+                }
+            }
+        }
+        self.update_accessible_descriptions(link);
+        imp.update_highlight();
 
-        self.queue_draw();
+        let is_traced = imp
+            .signal_trace
+            .borrow()
+            .as_ref()
+            .is_some_and(|trace| trace.links.contains(link));
+        if is_traced {
+            self.retrace_signal_path();
+        }
+    }
+
+    /// Update the accessible descriptions of the ports of a link that was added or removed,
+    /// so that screen readers can describe what the ports are connected to.
+    fn update_accessible_descriptions(&self, link: &Link) {
+        let port_links = self.imp().port_links.borrow();
+
+        for port in [link.output_port(), link.input_port()]
+            .into_iter()
+            .flatten()
+        {
+            let peers: Vec<String> = port_links
+                .get(&port)
+                .into_iter()
+                .flatten()
+                .filter_map(|link| {
+                    if link.output_port().as_ref() == Some(&port) {
+                        link.input_port()
+                    } else if link.input_port().as_ref() == Some(&port) {
+                        link.output_port()
+                    } else {
+                        None
+                    }
+                })
+                .map(|peer| {
+                    let node_name = peer
+                        .ancestor(Node::static_type())
+                        .and_downcast::<Node>()
+                        .map(|node| node.display_name())
+                        .unwrap_or_default();
+                    format!("{node_name}: {}", peer.name())
+                })
+                .collect();
+
+            let description = if peers.is_empty() {
+                "Not connected".to_string()
+            } else {
+                format!("Connected to {}", peers.join(", "))
+            };
+
+            port.update_property(&[gtk::accessible::Property::Description(&description)]);
+        }
+    }
+
+    /// Scroll the view so that the specified widget, which must be contained by the graph, is in the middle of the screen.
+    pub(super) fn scroll_to_widget(&self, widget: &impl IsA<gtk::Widget>) {
+        let Some(bounds) = widget.compute_bounds(self) else {
+            return;
+        };
+
+        let center = self
+            .imp()
+            .screen_space_to_canvas_space_transform()
+            .transform_point(&bounds.center());
+        self.center_on(&center);
     }
 
     pub fn clear(&mut self) {
-        self.imp().links.borrow_mut().clear();
-        for (node, _) in self.imp().nodes.borrow_mut().drain() {
+        let imp = self.imp();
+
+        // Abort any ongoing interaction, as the objects it refers to are about to be removed.
+        imp.dragged_node.replace(None);
+        imp.dragged_port.set(None);
+        imp.held_port.set(None);
+        imp.hovered.set(None);
+        imp.highlighted_links.borrow_mut().clear();
+        imp.highlighted_ports.borrow_mut().clear();
+        if imp.signal_trace.take().is_some() {
+            self.emit_by_name::<()>("signal-trace-changed", &[]);
+        }
+
+        imp.links.borrow_mut().clear();
+        imp.link_hit_areas.borrow_mut().clear();
+        imp.port_links.borrow_mut().clear();
+        imp.anchor_cache.borrow_mut().clear();
+        imp.route_cache.take();
+        for (node, _) in imp.nodes.borrow_mut().drain() {
             node.unparent();
         }
         self.queue_draw();
@@ -858,22 +2049,266 @@ impl GraphView {
         self.imp().nodes.borrow().get(node).copied()
     }
 
+    /// Get the area of the canvas that can be scrolled to.
+    ///
+    /// The canvas grows and shrinks with the nodes on it, always spanning the area
+    /// occupied by all nodes plus some margin around it.
+    ///
+    /// The returned area is in canvas-space.
+    pub(super) fn canvas_bounds(&self) -> graphene::Rect {
+        self.node_rects()
+            .into_iter()
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_else(graphene::Rect::zero)
+            .inset_r(-CANVAS_MARGIN, -CANVAS_MARGIN)
+    }
+
+    /// Get the area of the canvas that is currently visible on screen.
+    ///
+    /// The returned area is in canvas-space.
+    pub(super) fn visible_rect(&self) -> graphene::Rect {
+        self.imp()
+            .screen_space_to_canvas_space_transform()
+            .transform_bounds(&graphene::Rect::new(
+                0.0,
+                0.0,
+                self.width() as f32,
+                self.height() as f32,
+            ))
+    }
+
+    /// Get the area occupied by each node.
+    ///
+    /// The returned areas are in canvas-space.
+    pub(super) fn node_rects(&self) -> Vec<graphene::Rect> {
+        self.imp()
+            .nodes
+            .borrow()
+            .iter()
+            .map(|(node, point)| {
+                graphene::Rect::new(
+                    point.x(),
+                    point.y(),
+                    node.width() as f32,
+                    node.height() as f32,
+                )
+            })
+            .collect()
+    }
+
+    /// Get the start and end point of each link.
+    ///
+    /// The returned points are in canvas-space.
+    pub(super) fn link_lines(&self) -> Vec<(Point, Point)> {
+        let imp = self.imp();
+
+        imp.links
+            .borrow()
+            .iter()
+            .filter_map(|link| imp.get_link_coordinates(link))
+            .map(|((_, output_anchor), (_, input_anchor))| (output_anchor, input_anchor))
+            .collect()
+    }
+
+    /// Scroll the view so that the specified point is in the middle of the screen.
+    ///
+    /// The point is in canvas-space.
+    pub(super) fn center_on(&self, point: &Point) {
+        let imp = self.imp();
+        let zoom_factor = self.zoom_factor();
+
+        if let Some(ref hadjustment) = *imp.hadjustment.borrow() {
+            hadjustment
+                .set_value(f64::from(point.x()) * zoom_factor - f64::from(self.width()) / 2.0);
+        }
+        if let Some(ref vadjustment) = *imp.vadjustment.borrow() {
+            vadjustment
+                .set_value(f64::from(point.y()) * zoom_factor - f64::from(self.height()) / 2.0);
+        }
+    }
+
     pub(super) fn move_node(&self, widget: &Node, point: &Point) {
         let mut nodes = self.imp().nodes.borrow_mut();
         let node_point = nodes.get_mut(widget).expect("Node is not on the graph");
 
-        // Clamp the new position to within the graph, so a node can't be moved outside it and be lost.
-        node_point.set_x(point.x().clamp(
-            -(CANVAS_SIZE / 2.0) as f32,
-            (CANVAS_SIZE / 2.0) as f32 - widget.width() as f32,
-        ));
-        node_point.set_y(point.y().clamp(
-            -(CANVAS_SIZE / 2.0) as f32,
-            (CANVAS_SIZE / 2.0) as f32 - widget.height() as f32,
-        ));
+        // The canvas grows with the nodes on it, so there is no need to clamp the position.
+        *node_point = *point;
 
         self.queue_allocate();
     }
+
+    /// Select the specified node.
+    ///
+    /// If `extend` is `false`, all other nodes are unselected.
+    /// Otherwise, the selection of the node is toggled while keeping the rest of the selection.
+    pub(super) fn select_node(&self, node: &Node, extend: bool) {
+        if extend {
+            node.set_selected(!node.selected());
+        } else {
+            for other in self.imp().nodes.borrow().keys() {
+                other.set_selected(other == node);
+            }
+        }
+    }
+
+    pub fn clear_selection(&self) {
+        for node in self.imp().nodes.borrow().keys() {
+            node.set_selected(false);
+        }
+    }
+
+    /// Zoom and scroll the view so that all nodes are visible.
+    pub fn zoom_to_fit(&self) {
+        let rects = self.node_rects();
+        self.zoom_to_rects(&rects);
+    }
+
+    /// Zoom and scroll the view so that all selected nodes are visible.
+    ///
+    /// If no node is selected, this behaves like [`Self::zoom_to_fit()`].
+    pub fn zoom_to_selection(&self) {
+        let rects: Vec<graphene::Rect> = self
+            .imp()
+            .nodes
+            .borrow()
+            .iter()
+            .filter(|(node, _)| node.selected())
+            .map(|(node, point)| {
+                graphene::Rect::new(
+                    point.x(),
+                    point.y(),
+                    node.width() as f32,
+                    node.height() as f32,
+                )
+            })
+            .collect();
+
+        if rects.is_empty() {
+            self.zoom_to_fit();
+        } else {
+            self.zoom_to_rects(&rects);
+        }
+    }
+
+    /// Continuously move a node around while logging how long frames take.
+    ///
+    /// This simulates the user dragging the node with the most links, which moves those links
+    /// and the routes of the links around them on every frame, and is meant for measuring how
+    /// the graph performs with many links.
+    /// Each report is measured with the next link style, so that all ways of routing links are covered.
+    #[cfg(feature = "benchmark")]
+    pub fn start_benchmark(&self) {
+        const FRAMES_PER_REPORT: usize = 300;
+        const LINK_STYLES: [LinkStyle; 3] = [
+            LinkStyle::Bezier,
+            LinkStyle::Orthogonal,
+            LinkStyle::Straight,
+        ];
+
+        let frame_times: RefCell<Vec<i64>> = RefCell::default();
+        let last_frame: Cell<Option<i64>> = Cell::default();
+        let moved_node: RefCell<Option<(Node, Point)>> = RefCell::default();
+        let report: Cell<usize> = Cell::default();
+
+        self.set_link_style(LINK_STYLES[0]);
+
+        self.add_tick_callback(move |graph, frame_clock| {
+            let imp = graph.imp();
+
+            // Frame times are in microseconds.
+            let now = frame_clock.frame_time();
+            if let Some(last) = last_frame.replace(Some(now)) {
+                frame_times.borrow_mut().push(now - last);
+            }
+
+            // Nodes are added after the benchmark started, and may be removed again.
+            let mut moved_node = moved_node.borrow_mut();
+            if moved_node
+                .as_ref()
+                .map_or(true, |(node, _)| graph.node_position(node).is_none())
+            {
+                let mut link_counts: std::collections::HashMap<Node, usize> = Default::default();
+                for link in imp.links.borrow().iter() {
+                    for port in [link.output_port(), link.input_port()]
+                        .into_iter()
+                        .flatten()
+                    {
+                        if let Some(node) =
+                            port.ancestor(Node::static_type()).and_downcast::<Node>()
+                        {
+                            *link_counts.entry(node).or_default() += 1;
+                        }
+                    }
+                }
+
+                *moved_node = link_counts
+                    .into_iter()
+                    .max_by_key(|(_, count)| *count)
+                    .and_then(|(node, _)| graph.node_position(&node).map(|point| (node, point)));
+            }
+
+            // Move the node in a circle, one round every two seconds, like dragging it would.
+            if let Some((node, origin)) = moved_node.as_ref() {
+                let angle = now as f64 / 1_000_000.0 * std::f64::consts::PI;
+                graph.move_node(
+                    node,
+                    &Point::new(
+                        origin.x() + 100.0 * angle.cos() as f32,
+                        origin.y() + 100.0 * angle.sin() as f32,
+                    ),
+                );
+            }
+
+            let mut frame_times = frame_times.borrow_mut();
+            if frame_times.len() >= FRAMES_PER_REPORT {
+                let mean = frame_times.iter().sum::<i64>() as f64 / frame_times.len() as f64;
+                let max = frame_times.iter().copied().max().unwrap_or_default();
+                log::info!(
+                    "Benchmark: {} links, {:?} style, {:.1} links routed per frame, \
+                     mean frame time {:.2} ms, max frame time {:.2} ms",
+                    imp.links.borrow().len(),
+                    imp.link_style.get(),
+                    imp.routed_links.replace(0) as f64 / frame_times.len() as f64,
+                    mean / 1000.0,
+                    max as f64 / 1000.0
+                );
+                frame_times.clear();
+
+                report.set(report.get() + 1);
+                graph.set_link_style(LINK_STYLES[report.get() % LINK_STYLES.len()]);
+            }
+
+            glib::ControlFlow::Continue
+        });
+    }
+
+    /// Zoom and scroll the view so that the area covered by the specified rectangles (in canvas-space) is visible.
+    fn zoom_to_rects(&self, rects: &[graphene::Rect]) {
+        let imp = self.imp();
+
+        let Some(area) = rects.iter().copied().reduce(|a, b| a.union(&b)) else {
+            return;
+        };
+        let area = area.inset_r(-ZOOM_TO_FIT_PADDING, -ZOOM_TO_FIT_PADDING);
+
+        let zoom_factor = f64::min(
+            f64::from(self.width()) / f64::from(area.width()),
+            f64::from(self.height()) / f64::from(area.height()),
+        )
+        .clamp(Self::ZOOM_MIN, Self::ZOOM_MAX);
+        self.set_property("zoom-factor", zoom_factor);
+
+        // Update the adjustments to the new zoom level right away, instead of on the next allocation,
+        // so that scrolling to the area isn't limited by their old range.
+        if let Some(ref hadjustment) = *imp.hadjustment.borrow() {
+            imp.set_adjustment_values(self, hadjustment, gtk::Orientation::Horizontal);
+        }
+        if let Some(ref vadjustment) = *imp.vadjustment.borrow() {
+            imp.set_adjustment_values(self, vadjustment, gtk::Orientation::Vertical);
+        }
+
+        self.center_on(&area.center());
+    }
 }
 
 impl Default for GraphView {
@@ -14,22 +14,31 @@
 //
 // SPDX-License-Identifier: GPL-3.0-only
 
-use adw::{glib, prelude::*, subclass::prelude::*};
+use adw::{gdk, glib, prelude::*, subclass::prelude::*};
 use pipewire::spa::param::format::MediaType;
 
 use super::Port;
+use crate::{LinkOptions, LinkState};
 
 mod imp {
     use super::*;
 
-    use std::cell::Cell;
+    use std::cell::{Cell, RefCell};
 
     use once_cell::sync::Lazy;
 
     pub struct Link {
         pub output_port: glib::WeakRef<Port>,
         pub input_port: glib::WeakRef<Port>,
-        pub active: Cell<bool>,
+        pub state: Cell<LinkState>,
+        /// Why the link failed, if its state is [`LinkState::Error`].
+        pub error: RefCell<Option<String>>,
+        /// Whether the link does not keep its nodes running.
+        pub passive: Cell<bool>,
+        /// Whether the link stays after the client that created it disconnected.
+        pub linger: Cell<bool>,
+        /// Whether the link was created by Helvum, instead of another application.
+        pub created_by_helvum: Cell<bool>,
         pub media_type: Cell<MediaType>,
     }
 
@@ -38,7 +47,11 @@ mod imp {
             Self {
                 output_port: glib::WeakRef::default(),
                 input_port: glib::WeakRef::default(),
-                active: Cell::default(),
+                state: Cell::default(),
+                error: RefCell::default(),
+                passive: Cell::default(),
+                linger: Cell::default(),
+                created_by_helvum: Cell::default(),
                 media_type: Cell::new(MediaType::Unknown),
             }
         }
@@ -61,7 +74,21 @@ mod imp {
                     glib::ParamSpecObject::builder::<Port>("input-port")
                         .flags(glib::ParamFlags::READWRITE)
                         .build(),
-                    glib::ParamSpecBoolean::builder("active")
+                    glib::ParamSpecEnum::builder::<LinkState>("state")
+                        .flags(glib::ParamFlags::READWRITE)
+                        .build(),
+                    glib::ParamSpecString::builder("error")
+                        .flags(glib::ParamFlags::READWRITE)
+                        .build(),
+                    glib::ParamSpecBoolean::builder("passive")
+                        .default_value(false)
+                        .flags(glib::ParamFlags::READWRITE)
+                        .build(),
+                    glib::ParamSpecBoolean::builder("linger")
+                        .default_value(false)
+                        .flags(glib::ParamFlags::READWRITE)
+                        .build(),
+                    glib::ParamSpecBoolean::builder("created-by-helvum")
                         .default_value(false)
                         .flags(glib::ParamFlags::READWRITE)
                         .build(),
@@ -79,7 +106,11 @@ mod imp {
             match pspec.name() {
                 "output-port" => self.output_port.upgrade().to_value(),
                 "input-port" => self.input_port.upgrade().to_value(),
-                "active" => self.active.get().to_value(),
+                "state" => self.state.get().to_value(),
+                "error" => self.error.borrow().to_value(),
+                "passive" => self.passive.get().to_value(),
+                "linger" => self.linger.get().to_value(),
+                "created-by-helvum" => self.created_by_helvum.get().to_value(),
                 "media-type" => self.media_type.get().as_raw().to_value(),
                 _ => unimplemented!(),
             }
@@ -89,7 +120,13 @@ mod imp {
             match pspec.name() {
                 "output-port" => self.output_port.set(value.get().unwrap()),
                 "input-port" => self.input_port.set(value.get().unwrap()),
-                "active" => self.active.set(value.get().unwrap()),
+                "state" => self.state.set(value.get().unwrap()),
+                "error" => {
+                    self.error.replace(value.get().unwrap());
+                }
+                "passive" => self.passive.set(value.get().unwrap()),
+                "linger" => self.linger.set(value.get().unwrap()),
+                "created-by-helvum" => self.created_by_helvum.set(value.get().unwrap()),
                 "media-type" => self
                     .media_type
                     .set(MediaType::from_raw(value.get().unwrap())),
@@ -124,12 +161,47 @@ impl Link {
         self.set_property("input-port", port);
     }
 
-    pub fn active(&self) -> bool {
-        self.property("active")
+    pub fn state(&self) -> LinkState {
+        self.property("state")
+    }
+
+    pub fn set_state(&self, state: LinkState) {
+        self.set_property("state", state);
     }
 
-    pub fn set_active(&self, active: bool) {
-        self.set_property("active", active);
+    /// Get why the link failed, if its state is [`LinkState::Error`].
+    pub fn error(&self) -> Option<String> {
+        self.property("error")
+    }
+
+    pub fn set_error(&self, error: Option<&str>) {
+        self.set_property("error", error);
+    }
+
+    /// Get whether the link does not keep its nodes running.
+    pub fn passive(&self) -> bool {
+        self.property("passive")
+    }
+
+    pub fn set_passive(&self, passive: bool) {
+        self.set_property("passive", passive);
+    }
+
+    /// Get whether the link stays after the client that created it disconnected.
+    pub fn linger(&self) -> bool {
+        self.property("linger")
+    }
+
+    pub fn set_linger(&self, linger: bool) {
+        self.set_property("linger", linger);
+    }
+
+    pub fn created_by_helvum(&self) -> bool {
+        self.property("created-by-helvum")
+    }
+
+    pub fn set_created_by_helvum(&self, created_by_helvum: bool) {
+        self.set_property("created-by-helvum", created_by_helvum);
     }
 
     pub fn media_type(&self) -> MediaType {
@@ -146,3 +218,17 @@ impl Default for Link {
         Self::new()
     }
 }
+
+/// Get the options for a link that is created while the specified modifier keys are held.
+///
+/// Holding Ctrl creates a link that is removed again when Helvum closes,
+/// holding Shift creates a passive link, which does not keep its nodes running.
+///
+/// Whether stream targets are pinned is not decided by modifiers, but by the user's settings.
+pub fn link_options_for_modifiers(modifiers: gdk::ModifierType) -> LinkOptions {
+    LinkOptions {
+        linger: !modifiers.contains(gdk::ModifierType::CONTROL_MASK),
+        passive: modifiers.contains(gdk::ModifierType::SHIFT_MASK),
+        pin_target: false,
+    }
+}
@@ -0,0 +1,263 @@
+// Copyright 2021 Tom A. Wagner <tom.a.wagner@protonmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License version 3 as published by
+// the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use adw::{
+    glib::{self, clone},
+    gtk::{self, graphene},
+    prelude::*,
+    subclass::prelude::*,
+};
+
+use super::GraphView;
+
+mod imp {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    use log::warn;
+
+    /// An overview of the whole canvas of a [`GraphView`] that can be used to navigate it.
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::Minimap)]
+    pub struct Minimap {
+        #[property(get, set = Self::set_graph_view)]
+        pub graph_view: RefCell<Option<GraphView>>,
+        layout_changed_handler: RefCell<Option<glib::SignalHandlerId>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for Minimap {
+        const NAME: &'static str = "HelvumMinimap";
+        type Type = super::Minimap;
+        type ParentType = gtk::Widget;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.set_css_name("minimap");
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for Minimap {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().add_css_class("osd");
+            self.obj().set_overflow(gtk::Overflow::Hidden);
+            self.obj()
+                .set_cursor(gtk::gdk::Cursor::from_name("pointer", None).as_ref());
+
+            self.setup_navigation();
+        }
+
+        fn dispose(&self) {
+            self.disconnect_graph_view();
+        }
+    }
+
+    impl WidgetImpl for Minimap {
+        fn request_mode(&self) -> gtk::SizeRequestMode {
+            gtk::SizeRequestMode::ConstantSize
+        }
+
+        fn measure(&self, _orientation: gtk::Orientation, _for_size: i32) -> (i32, i32, i32, i32) {
+            (Self::SIZE, Self::SIZE, -1, -1)
+        }
+
+        fn snapshot(&self, snapshot: &gtk::Snapshot) {
+            let Some(graph) = self.graph_view.borrow().clone() else {
+                return;
+            };
+            let widget = &*self.obj();
+            let (scale, offset) = self.canvas_to_minimap(&graph);
+            let to_minimap = |point: &graphene::Point| {
+                (
+                    f64::from(point.x() * scale + offset.x()),
+                    f64::from(point.y() * scale + offset.y()),
+                )
+            };
+
+            let style_context = widget.style_context();
+            let node_color = style_context
+                .lookup_color("window_fg_color")
+                .unwrap_or(gtk::gdk::RGBA::WHITE);
+            let link_color = style_context
+                .lookup_color("media-type-unknown")
+                .expect("color not found");
+            let viewport_color = style_context
+                .lookup_color("accent_color")
+                .unwrap_or(gtk::gdk::RGBA::BLUE);
+
+            for rect in graph.node_rects() {
+                snapshot.append_color(
+                    &node_color.with_alpha(0.5),
+                    &graphene::Rect::new(
+                        rect.x() * scale + offset.x(),
+                        rect.y() * scale + offset.y(),
+                        f32::max(rect.width() * scale, 1.0),
+                        f32::max(rect.height() * scale, 1.0),
+                    ),
+                );
+            }
+
+            let cr = snapshot.append_cairo(&graphene::Rect::new(
+                0.0,
+                0.0,
+                widget.width() as f32,
+                widget.height() as f32,
+            ));
+
+            cr.set_line_width(1.0);
+            cr.set_source_rgba(
+                link_color.red().into(),
+                link_color.green().into(),
+                link_color.blue().into(),
+                link_color.alpha().into(),
+            );
+            for (output_anchor, input_anchor) in graph.link_lines() {
+                let (output_x, output_y) = to_minimap(&output_anchor);
+                let (input_x, input_y) = to_minimap(&input_anchor);
+                cr.move_to(output_x, output_y);
+                cr.line_to(input_x, input_y);
+            }
+            if let Err(e) = cr.stroke() {
+                warn!("Failed to draw minimap links: {}", e);
+            }
+
+            let visible_rect = graph.visible_rect();
+            let (x, y) = to_minimap(&visible_rect.top_left());
+            cr.rectangle(
+                x,
+                y,
+                f64::from(visible_rect.width() * scale),
+                f64::from(visible_rect.height() * scale),
+            );
+            cr.set_line_width(2.0);
+            cr.set_source_rgba(
+                viewport_color.red().into(),
+                viewport_color.green().into(),
+                viewport_color.blue().into(),
+                viewport_color.alpha().into(),
+            );
+            if let Err(e) = cr.stroke() {
+                warn!("Failed to draw minimap viewport: {}", e);
+            }
+        }
+    }
+
+    impl Minimap {
+        /// Width and height of the minimap in pixels.
+        const SIZE: i32 = 160;
+
+        fn set_graph_view(&self, graph_view: Option<GraphView>) {
+            self.disconnect_graph_view();
+
+            if let Some(ref graph_view) = graph_view {
+                let handler = graph_view.connect_local(
+                    "layout-changed",
+                    false,
+                    clone!(@weak self as imp => @default-return None, move |_| {
+                        imp.obj().queue_draw();
+                        None
+                    }),
+                );
+                self.layout_changed_handler.replace(Some(handler));
+            }
+
+            self.graph_view.replace(graph_view);
+            self.obj().queue_draw();
+        }
+
+        fn disconnect_graph_view(&self) {
+            if let (Some(graph_view), Some(handler)) = (
+                self.graph_view.borrow().as_ref(),
+                self.layout_changed_handler.take(),
+            ) {
+                graph_view.disconnect(handler);
+            }
+        }
+
+        /// Get the scale and offset that map canvas-space coordinates of the graph to coordinates on the minimap.
+        ///
+        /// The whole canvas is fit into the minimap while keeping its aspect ratio.
+        fn canvas_to_minimap(&self, graph: &GraphView) -> (f32, graphene::Point) {
+            let widget = self.obj();
+            let width = widget.width() as f32;
+            let height = widget.height() as f32;
+            let bounds = graph.canvas_bounds();
+
+            let scale = f32::min(width / bounds.width(), height / bounds.height());
+            let offset = graphene::Point::new(
+                (width - bounds.width() * scale) / 2.0 - bounds.x() * scale,
+                (height - bounds.height() * scale) / 2.0 - bounds.y() * scale,
+            );
+
+            (scale, offset)
+        }
+
+        /// Scroll the graph so that the point under the specified coordinates on the minimap is centered.
+        fn navigate_to(&self, x: f64, y: f64) {
+            let Some(graph) = self.graph_view.borrow().clone() else {
+                return;
+            };
+            let (scale, offset) = self.canvas_to_minimap(&graph);
+
+            graph.center_on(&graphene::Point::new(
+                (x as f32 - offset.x()) / scale,
+                (y as f32 - offset.y()) / scale,
+            ));
+        }
+
+        fn setup_navigation(&self) {
+            let drag_controller = gtk::GestureDrag::new();
+
+            drag_controller.connect_drag_begin(|drag_controller, x, y| {
+                let widget = drag_controller
+                    .widget()
+                    .downcast::<super::Minimap>()
+                    .expect("Widget should be a minimap");
+
+                widget.imp().navigate_to(x, y);
+            });
+            drag_controller.connect_drag_update(|drag_controller, x, y| {
+                let widget = drag_controller
+                    .widget()
+                    .downcast::<super::Minimap>()
+                    .expect("Widget should be a minimap");
+                let (start_x, start_y) = drag_controller
+                    .start_point()
+                    .expect("Drag has no start point");
+
+                widget.imp().navigate_to(start_x + x, start_y + y);
+            });
+
+            self.obj().add_controller(drag_controller);
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct Minimap(ObjectSubclass<imp::Minimap>)
+        @extends gtk::Widget;
+}
+
+impl Minimap {
+    pub fn new(graph_view: &GraphView) -> Self {
+        glib::Object::builder()
+            .property("graph-view", graph_view)
+            .build()
+    }
+}
@@ -24,5 +24,9 @@ mod port_handle;
 pub use port_handle::*;
 mod link;
 pub use link::*;
+mod minimap;
+pub use minimap::*;
+mod routing;
+pub use routing::LinkStyle;
 mod zoomentry;
 pub use zoomentry::*;
@@ -14,18 +14,44 @@
 //
 // SPDX-License-Identifier: GPL-3.0-only
 
-use adw::{glib, gtk, prelude::*, subclass::prelude::*};
+use std::{cmp::Ordering, iter::Peekable, str::Chars};
+
+use adw::{
+    glib::{self, clone, subclass::Signal},
+    gtk,
+    prelude::*,
+    subclass::prelude::*,
+};
 use pipewire::spa::utils::Direction;
 
-use super::Port;
+use super::{GraphView, Port};
+
+/// The order that the ports of a node are shown in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "HelvumPortSortMode")]
+pub enum PortSortMode {
+    /// Sort by the shown name of the port, comparing numbers in it by their value.
+    #[default]
+    Natural,
+    /// Sort by the index of the port on its node.
+    PortId,
+    /// Sort by the position of the audio channel of the port, e.g. front left before front right.
+    ChannelPosition,
+}
+
+/// Audio channel positions in the order they are defined by SPA.
+const CHANNEL_POSITIONS: &[&str] = &[
+    "MONO", "FL", "FR", "FC", "LFE", "SL", "SR", "FLC", "FRC", "RC", "RL", "RR", "TC", "TFL",
+    "TFC", "TFR", "TRL", "TRC", "TRR", "RLC", "RRC", "FLW", "FRW", "LFE2", "FLH", "FCH", "FRH",
+    "TFLC", "TFRC", "TSL", "TSR", "LLFE", "RLFE", "BC", "BLC", "BRC",
+];
 
 mod imp {
     use super::*;
 
-    use std::{
-        cell::{Cell, RefCell},
-        collections::HashSet,
-    };
+    use std::cell::{Cell, RefCell};
+
+    use once_cell::sync::Lazy;
 
     #[derive(glib::Properties, gtk::CompositeTemplate, Default)]
     #[properties(wrapper_type = super::Node)]
@@ -33,16 +59,14 @@ mod imp {
     pub struct Node {
         #[property(get, set, construct_only)]
         pub(super) pipewire_id: Cell<u32>,
-        #[property(
-            name = "node-name", type = String,
-            get = |this: &Self| this.node_name.text().to_string(),
-            set = |this: &Self, val| {
-                this.node_name.set_text(val);
-                this.node_name.set_tooltip_text(Some(val));
-            }
-        )]
+        /// The name of the node as reported by pipewire.
+        #[property(get, set = Self::set_node_name)]
+        pub(super) node_name: RefCell<String>,
+        /// A name given to the node by the user, which is shown instead of the node name if set.
+        #[property(get, set = Self::set_custom_label)]
+        pub(super) custom_label: RefCell<String>,
         #[template_child]
-        pub(super) node_name: TemplateChild<gtk::Label>,
+        pub(super) title: TemplateChild<gtk::Label>,
         #[property(
             name = "media-name", type = String,
             get = |this: &Self| this.media_name.text().to_string(),
@@ -54,11 +78,30 @@ mod imp {
         )]
         #[template_child]
         pub(super) media_name: TemplateChild<gtk::Label>,
+        /// The name of the node that the session manager keeps this node routed to, empty if it is routed freely.
+        #[property(get, set = Self::set_pinned_target)]
+        pub(super) pinned_target: RefCell<String>,
+        #[template_child]
+        pub(super) pin_icon: TemplateChild<gtk::Image>,
         #[template_child]
         pub(super) separator: TemplateChild<gtk::Separator>,
         #[template_child]
         pub(super) port_grid: TemplateChild<gtk::Grid>,
-        pub(super) ports: RefCell<HashSet<Port>>,
+        #[template_child]
+        pub(super) collapse_button: TemplateChild<gtk::ToggleButton>,
+        /// Whether the port list of the node is hidden.
+        #[property(get, set = Self::set_collapsed)]
+        pub(super) collapsed: Cell<bool>,
+        #[property(get, set = Self::set_selected)]
+        pub(super) selected: Cell<bool>,
+        #[property(get, set = Self::set_port_sort_mode, builder(PortSortMode::Natural))]
+        pub(super) port_sort_mode: Cell<PortSortMode>,
+        /// Input ports of the node, in the order they are shown in.
+        pub(super) ports_in: RefCell<Vec<Port>>,
+        /// Output ports of the node, in the order they are shown in.
+        pub(super) ports_out: RefCell<Vec<Port>>,
+        /// Incremented whenever the ports may have moved inside the node, e.g. because they were sorted again.
+        pub(super) layout_serial: Cell<u32>,
     }
 
     #[glib::object_subclass]
@@ -73,6 +116,30 @@ mod imp {
             klass.bind_template();
 
             klass.set_css_name("node");
+            klass.set_accessible_role(gtk::AccessibleRole::Group);
+
+            klass.install_action("node.rename", None, |node, _, _| {
+                node.imp().show_rename_dialog();
+            });
+            klass.install_action("node.reset-label", None, |node, _, _| {
+                node.emit_by_name::<()>("label-edited", &[&""]);
+            });
+            klass.install_action("node.trace-downstream", None, |node, _, _| {
+                if let Some(graph) = node
+                    .ancestor(GraphView::static_type())
+                    .and_downcast::<GraphView>()
+                {
+                    graph.trace_signal_path(node, Direction::Output);
+                }
+            });
+            klass.install_action("node.trace-upstream", None, |node, _, _| {
+                if let Some(graph) = node
+                    .ancestor(GraphView::static_type())
+                    .and_downcast::<GraphView>()
+                {
+                    graph.trace_signal_path(node, Direction::Input);
+                }
+            });
         }
 
         fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
@@ -89,8 +156,18 @@ mod imp {
             self.port_grid.set_direction(gtk::TextDirection::Ltr);
 
             // Display a grab cursor when the mouse is over the label so the user knows the node can be dragged.
-            self.node_name
+            self.title
                 .set_cursor(gtk::gdk::Cursor::from_name("grab", None).as_ref());
+
+            self.obj().set_focusable(true);
+            // There is no custom label to reset yet.
+            self.obj().action_set_enabled("node.reset-label", false);
+
+            self.obj()
+                .bind_property("collapsed", &*self.collapse_button, "active")
+                .bidirectional()
+                .sync_create()
+                .build();
         }
 
         fn dispose(&self) {
@@ -98,50 +175,236 @@ mod imp {
                 child.unparent();
             }
         }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![Signal::builder("label-edited")
+                    // Provide the new label, which is empty if the label was reset.
+                    .param_types([String::static_type()])
+                    .build()]
+            });
+
+            SIGNALS.as_ref()
+        }
     }
 
     impl WidgetImpl for Node {}
 
     impl Node {
-        /// Update the internal ports grid to reflect the ports stored in the ports set.
+        /// Sort all ports again and rebuild the ports grid from them, e.g. after the sort mode changed.
         pub fn update_ports(&self) {
-            // We first remove all ports from the grid, then re-add them all, so that
-            // ports that have been removed do not leave gaps in the grid.
-
+            // We first remove all ports from the grid, then re-add them all in their new order.
             while let Some(ref child) = self.port_grid.first_child() {
                 self.port_grid.remove(child);
             }
 
-            let ports = self.ports.borrow();
+            for (column, ports) in [(0, &self.ports_in), (1, &self.ports_out)] {
+                let mut ports = ports.borrow_mut();
+                ports.sort_by(|a, b| self.compare_ports(a, b));
+
+                for (i, port) in ports.iter().enumerate() {
+                    self.port_grid
+                        .attach(port, column, i.try_into().unwrap(), 1, 1);
+                }
+            }
+
+            self.ports_changed();
+        }
+
+        /// Insert a port into the ports grid at its sorted position, moving the ports after it down by one row.
+        pub fn insert_port(&self, port: Port) {
+            let (column, ports) = self.column_of(&port);
+            let mut ports = ports.borrow_mut();
+
+            // Insert after ports that compare equal, so that ports keep the order they were added in.
+            let index = ports
+                .partition_point(|other| self.compare_ports(other, &port) != Ordering::Greater);
+            for (row, other) in ports.iter().enumerate().skip(index) {
+                self.set_row(other, row + 1);
+            }
+
+            self.port_grid
+                .attach(&port, column, index.try_into().unwrap(), 1, 1);
+            ports.insert(index, port);
+        }
+
+        /// Remove a port from the ports grid, moving the ports after it up by one row.
+        ///
+        /// Returns whether the port was on the node.
+        pub fn take_port(&self, port: &Port) -> bool {
+            let (_, ports) = self.column_of(port);
+            let mut ports = ports.borrow_mut();
 
-            let mut ports_out = Vec::new();
-            let mut ports_in = Vec::new();
+            let Some(index) = ports.iter().position(|other| other == port) else {
+                return false;
+            };
+            ports.remove(index);
+            self.port_grid.remove(port);
 
-            ports
-                .iter()
-                .for_each(|port| match Direction::from_raw(port.direction()) {
-                    Direction::Output => {
-                        ports_out.push(port);
-                    }
-                    Direction::Input => {
-                        ports_in.push(port);
-                    }
-                    _ => unreachable!(),
-                });
+            for (row, other) in ports.iter().enumerate().skip(index) {
+                self.set_row(other, row);
+            }
 
-            ports_out.sort_unstable_by_key(|port| port.name());
-            ports_in.sort_unstable_by_key(|port| port.name());
+            true
+        }
 
+        /// Update everything that depends on the ports after they were added, removed or moved.
+        pub fn ports_changed(&self) {
             // In case no ports have been added to the port, hide the seperator as it is not needed
             self.separator
-                .set_visible(!ports_out.is_empty() || !ports_in.is_empty());
+                .set_visible(!self.collapsed.get() && self.has_ports());
+            self.layout_serial
+                .set(self.layout_serial.get().wrapping_add(1));
+        }
 
-            for (i, port) in ports_in.into_iter().enumerate() {
-                self.port_grid.attach(port, 0, i.try_into().unwrap(), 1, 1);
+        fn has_ports(&self) -> bool {
+            !self.ports_in.borrow().is_empty() || !self.ports_out.borrow().is_empty()
+        }
+
+        /// Get the grid column of the port, and the list of ports shown in that column.
+        pub fn column_of(&self, port: &Port) -> (i32, &RefCell<Vec<Port>>) {
+            match Direction::from_raw(port.direction()) {
+                Direction::Input => (0, &self.ports_in),
+                Direction::Output => (1, &self.ports_out),
+                _ => unreachable!(),
+            }
+        }
+
+        /// Compare two ports of the node by the order they should be shown in.
+        fn compare_ports(&self, a: &Port, b: &Port) -> Ordering {
+            // Keep monitor ports together after the other ports.
+            a.is_monitor()
+                .cmp(&b.is_monitor())
+                .then_with(|| compare_ports(a, b, self.port_sort_mode.get()))
+        }
+
+        /// Move a port that is already in the ports grid to another row.
+        fn set_row(&self, port: &Port, row: usize) {
+            let Some(layout_manager) = self.port_grid.layout_manager() else {
+                return;
+            };
+
+            if let Ok(layout_child) = layout_manager
+                .layout_child(port)
+                .downcast::<gtk::GridLayoutChild>()
+            {
+                layout_child.set_row(row.try_into().unwrap());
             }
+        }
 
-            for (i, port) in ports_out.into_iter().enumerate() {
-                self.port_grid.attach(port, 1, i.try_into().unwrap(), 1, 1);
+        fn set_collapsed(&self, collapsed: bool) {
+            self.collapsed.set(collapsed);
+            self.layout_serial
+                .set(self.layout_serial.get().wrapping_add(1));
+
+            self.port_grid.set_visible(!collapsed);
+            self.separator.set_visible(!collapsed && self.has_ports());
+
+            if collapsed {
+                self.collapse_button.set_icon_name("pan-end-symbolic");
+                self.collapse_button.set_tooltip_text(Some("Show ports"));
+                self.obj().add_css_class("collapsed");
+            } else {
+                self.collapse_button.set_icon_name("pan-down-symbolic");
+                self.collapse_button.set_tooltip_text(Some("Hide ports"));
+                self.obj().remove_css_class("collapsed");
+            }
+        }
+
+        fn set_node_name(&self, node_name: String) {
+            self.node_name.replace(node_name);
+            self.update_title();
+        }
+
+        fn set_custom_label(&self, custom_label: String) {
+            self.custom_label.replace(custom_label);
+            self.update_title();
+            self.obj()
+                .action_set_enabled("node.reset-label", !self.custom_label.borrow().is_empty());
+        }
+
+        fn set_pinned_target(&self, pinned_target: String) {
+            self.pin_icon.set_visible(!pinned_target.is_empty());
+            self.pin_icon.set_tooltip_text(Some(&format!(
+                "Kept linked to {pinned_target} by the session manager"
+            )));
+            self.pinned_target.replace(pinned_target);
+        }
+
+        /// Show the custom label if there is one, and the node name otherwise.
+        fn update_title(&self) {
+            let node_name = self.node_name.borrow();
+            let custom_label = self.custom_label.borrow();
+
+            let title = self.obj().display_name();
+            self.title.set_text(&title);
+            // Keep the original name of renamed nodes discoverable.
+            if custom_label.is_empty() {
+                self.title.set_tooltip_text(Some(&node_name));
+            } else {
+                self.title
+                    .set_tooltip_text(Some(&format!("{custom_label}\n({node_name})")));
+            }
+            self.obj()
+                .update_property(&[gtk::accessible::Property::Label(&title)]);
+        }
+
+        /// Ask the user for a new label, emitting the "label-edited" signal if one was entered.
+        fn show_rename_dialog(&self) {
+            let obj = self.obj();
+            let window = obj.root().and_downcast::<gtk::Window>();
+
+            let dialog = adw::MessageDialog::new(window.as_ref(), Some("Rename Node"), None);
+            dialog.set_body(&format!(
+                "The new name is shown for \"{}\", also after restarting.",
+                self.node_name.borrow()
+            ));
+            dialog.add_responses(&[("cancel", "_Cancel"), ("rename", "_Rename")]);
+            dialog.set_response_appearance("rename", adw::ResponseAppearance::Suggested);
+            dialog.set_default_response(Some("rename"));
+            dialog.set_close_response("cancel");
+
+            let list = gtk::ListBox::new();
+            list.set_selection_mode(gtk::SelectionMode::None);
+            list.add_css_class("boxed-list");
+
+            let entry = adw::EntryRow::new();
+            entry.set_title("Name");
+            entry.set_text(&obj.display_name());
+            entry.set_activates_default(true);
+            list.append(&entry);
+
+            dialog.set_extra_child(Some(&list));
+
+            dialog.connect_response(
+                Some("rename"),
+                clone!(@weak obj, @weak entry => move |_, _| {
+                    let label = entry.text().trim().to_string();
+                    // Giving the node its own name back is the same as resetting its label.
+                    let label = if label == *obj.imp().node_name.borrow() {
+                        String::new()
+                    } else {
+                        label
+                    };
+                    obj.emit_by_name::<()>("label-edited", &[&label]);
+                }),
+            );
+
+            dialog.present();
+        }
+
+        fn set_port_sort_mode(&self, sort_mode: PortSortMode) {
+            self.port_sort_mode.set(sort_mode);
+            self.update_ports();
+        }
+
+        fn set_selected(&self, selected: bool) {
+            self.selected.set(selected);
+
+            if selected {
+                self.obj().add_css_class("selected");
+            } else {
+                self.obj().remove_css_class("selected");
             }
         }
     }
@@ -160,18 +423,226 @@ impl Node {
             .build()
     }
 
+    /// Get the name that is shown for the node, which is its custom label if it has one.
+    pub fn display_name(&self) -> String {
+        let imp = self.imp();
+        let custom_label = imp.custom_label.borrow();
+        if custom_label.is_empty() {
+            imp.node_name.borrow().clone()
+        } else {
+            custom_label.clone()
+        }
+    }
+
     pub fn add_port(&self, port: Port) {
+        self.add_ports(vec![port]);
+    }
+
+    /// Add several ports at once, e.g. all ports of a device that just appeared.
+    ///
+    /// A single port is inserted at its place among the existing ports,
+    /// while several ports are added by sorting all ports and laying them out only once.
+    pub fn add_ports(&self, ports: Vec<Port>) {
         let imp = self.imp();
-        imp.ports.borrow_mut().insert(port);
+
+        if let [port] = &ports[..] {
+            imp.insert_port(port.clone());
+            imp.ports_changed();
+            return;
+        }
+
+        for port in ports {
+            let (_, column_ports) = imp.column_of(&port);
+            column_ports.borrow_mut().push(port);
+        }
         imp.update_ports();
     }
 
     pub fn remove_port(&self, port: &Port) {
         let imp = self.imp();
-        if imp.ports.borrow_mut().remove(port) {
-            imp.update_ports();
+        if imp.take_port(port) {
+            imp.ports_changed();
         } else {
             log::warn!("Tried to remove non-existant port widget from node");
         }
     }
+
+    /// Get all ports of the node, inputs first.
+    pub fn ports(&self) -> Vec<Port> {
+        let imp = self.imp();
+        imp.ports_in
+            .borrow()
+            .iter()
+            .chain(imp.ports_out.borrow().iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Sort the ports again, e.g. after the names they are sorted by changed.
+    pub fn sort_ports(&self) {
+        self.imp().update_ports();
+    }
+
+    /// Get a number that changes whenever the ports may have moved inside the node.
+    ///
+    /// Together with the size of the node, this tells whether positions of ports computed earlier are still valid.
+    pub fn layout_serial(&self) -> u32 {
+        self.imp().layout_serial.get()
+    }
+
+    /// Get the point that links of hidden ports should be attached to while the node is collapsed.
+    ///
+    /// The point is located on the edge of the node that ports of the specified direction would be on,
+    /// and is relative to the node.
+    pub fn collapsed_link_anchor(&self, direction: Direction) -> gtk::graphene::Point {
+        gtk::graphene::Point::new(
+            match direction {
+                Direction::Output => self.width() as f32,
+                Direction::Input => 0.0,
+                _ => unreachable!(),
+            },
+            self.height() as f32 / 2.0,
+        )
+    }
+}
+
+/// Compare two ports of the same node according to the sort mode.
+fn compare_ports(a: &Port, b: &Port, sort_mode: PortSortMode) -> Ordering {
+    let by_name = || natural_cmp(&a.display_name(), &b.display_name());
+
+    match sort_mode {
+        PortSortMode::Natural => by_name(),
+        PortSortMode::PortId => a
+            .port_index()
+            .unwrap_or(u32::MAX)
+            .cmp(&b.port_index().unwrap_or(u32::MAX))
+            .then_with(|| a.pipewire_id().cmp(&b.pipewire_id())),
+        PortSortMode::ChannelPosition => channel_position(&a.audio_channel())
+            .cmp(&channel_position(&b.audio_channel()))
+            .then_with(by_name),
+    }
+}
+
+/// Get the sort position of an audio channel, with unknown channels last.
+fn channel_position(channel: &str) -> usize {
+    if let Some(position) = CHANNEL_POSITIONS.iter().position(|pos| *pos == channel) {
+        return position;
+    }
+
+    // Auxiliary channels are numbered, e.g. "AUX0", and are sorted after all named channels.
+    channel
+        .strip_prefix("AUX")
+        .and_then(|n| n.parse::<usize>().ok())
+        .map_or(usize::MAX, |n| CHANNEL_POSITIONS.len().saturating_add(n))
+}
+
+/// Compare two strings, treating runs of digits as numbers so that e.g. "AUX2" comes before "AUX10".
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        let ordering = match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_digits = take_digits(&mut a);
+                let b_digits = take_digits(&mut b);
+                let a_digits = a_digits.trim_start_matches('0');
+                let b_digits = b_digits.trim_start_matches('0');
+
+                // Without leading zeros, the longer number is the larger one.
+                a_digits
+                    .len()
+                    .cmp(&b_digits.len())
+                    .then_with(|| a_digits.cmp(b_digits))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.cmp(&y);
+                a.next();
+                b.next();
+                ordering
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Take the run of digits at the start of the iterator.
+fn take_digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_cmp_compares_numbers_by_value() {
+        assert_eq!(natural_cmp("AUX2", "AUX10"), Ordering::Less);
+        assert_eq!(natural_cmp("AUX10", "AUX2"), Ordering::Greater);
+        assert_eq!(natural_cmp("AUX10", "AUX10"), Ordering::Equal);
+        assert_eq!(natural_cmp("9", "10"), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_cmp_compares_text_after_equal_numbers() {
+        assert_eq!(
+            natural_cmp("capture_1_left", "capture_1_right"),
+            Ordering::Less
+        );
+        assert_eq!(natural_cmp("capture_1", "capture_1_left"), Ordering::Less);
+        assert_eq!(
+            natural_cmp("capture_2_left", "capture_10_left"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn natural_cmp_ignores_leading_zeros() {
+        assert_eq!(natural_cmp("AUX02", "AUX2"), Ordering::Equal);
+        assert_eq!(natural_cmp("AUX007", "AUX10"), Ordering::Less);
+        assert_eq!(natural_cmp("AUX0", "AUX1"), Ordering::Less);
+        assert_eq!(natural_cmp("AUX00", "AUX0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn natural_cmp_compares_mixed_runs() {
+        assert_eq!(natural_cmp("a1b2", "a1b10"), Ordering::Less);
+        assert_eq!(natural_cmp("a10b1", "a9b2"), Ordering::Greater);
+        // Digits sort before letters, as in plain string comparison.
+        assert_eq!(natural_cmp("1a", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("", "a"), Ordering::Less);
+    }
+
+    #[test]
+    fn channel_position_orders_named_channels() {
+        let mut channels = vec!["LFE", "FC", "FR", "FL"];
+        channels.sort_by_key(|channel| channel_position(channel));
+
+        assert_eq!(channels, vec!["FL", "FR", "FC", "LFE"]);
+        assert!(channel_position("MONO") < channel_position("FL"));
+    }
+
+    #[test]
+    fn channel_position_orders_auxiliary_channels_after_named_channels() {
+        assert!(channel_position("BRC") < channel_position("AUX0"));
+        assert!(channel_position("AUX2") < channel_position("AUX10"));
+    }
+
+    #[test]
+    fn channel_position_sorts_unknown_channels_last() {
+        for unknown in ["", "UNK", "AUX", "AUXfoo", "fl"] {
+            assert_eq!(channel_position(unknown), usize::MAX, "{unknown:?}");
+            assert!(channel_position("AUX63") < channel_position(unknown));
+        }
+    }
 }
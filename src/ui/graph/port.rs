@@ -23,12 +23,25 @@ use adw::{
 };
 use pipewire::spa::utils::Direction;
 
-use super::PortHandle;
+use super::{link_options_for_modifiers, GraphView, PortHandle};
+
+/// Which name of a port is shown as its label.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "HelvumPortLabelMode")]
+pub enum PortLabelMode {
+    /// The `port.name` property.
+    #[default]
+    Name,
+    /// The `port.alias` property.
+    Alias,
+    /// The `audio.channel` property.
+    Channel,
+}
 
 mod imp {
     use super::*;
 
-    use std::cell::{Cell, OnceCell};
+    use std::cell::{Cell, OnceCell, RefCell};
 
     use once_cell::sync::Lazy;
     use pipewire::spa::{param::format::MediaType, utils::Direction};
@@ -53,14 +66,29 @@ mod imp {
             construct_only
         )]
         pub(super) direction: Cell<Direction>,
-        #[property(
-            name = "name", type = String,
-            get = |this: &Self| this.label.text().to_string(),
-            set = |this: &Self, val| {
-                this.label.set_text(val);
-                this.label.set_tooltip_text(Some(val));
-            }
-        )]
+        #[property(get, set = Self::set_name)]
+        pub(super) name: RefCell<String>,
+        #[property(get, set = Self::set_alias)]
+        pub(super) alias: RefCell<String>,
+        #[property(get, set = Self::set_audio_channel)]
+        pub(super) audio_channel: RefCell<String>,
+        /// The index of the port on its node, as given by the `port.id` property.
+        pub(super) port_index: Cell<Option<u32>>,
+        #[property(get, set = Self::set_object_path)]
+        pub(super) object_path: RefCell<String>,
+        /// Description of the negotiated format of the port.
+        #[property(get, set = Self::set_format)]
+        pub(super) format: RefCell<String>,
+        #[property(get, set)]
+        pub(super) is_monitor: Cell<bool>,
+        /// Whether the port belongs to a physical device.
+        #[property(get, set = Self::set_is_physical)]
+        pub(super) is_physical: Cell<bool>,
+        /// Whether the port is the start or end of a signal chain.
+        #[property(get, set = Self::set_is_terminal)]
+        pub(super) is_terminal: Cell<bool>,
+        #[property(get, set = Self::set_label_mode, builder(PortLabelMode::Name))]
+        pub(super) label_mode: Cell<PortLabelMode>,
         #[template_child]
         pub(super) label: TemplateChild<gtk::Label>,
         #[template_child]
@@ -73,6 +101,16 @@ mod imp {
                 pipewire_id: OnceCell::default(),
                 media_type: Cell::new(MediaType::Unknown),
                 direction: Cell::new(Direction::Output),
+                name: RefCell::default(),
+                alias: RefCell::default(),
+                audio_channel: RefCell::default(),
+                port_index: Cell::default(),
+                object_path: RefCell::default(),
+                format: RefCell::default(),
+                is_monitor: Cell::default(),
+                is_physical: Cell::default(),
+                is_terminal: Cell::default(),
+                label_mode: Cell::default(),
                 label: TemplateChild::default(),
                 handle: TemplateChild::default(),
             }
@@ -87,6 +125,7 @@ mod imp {
 
         fn class_init(klass: &mut Self::Class) {
             klass.set_css_name("port");
+            klass.set_accessible_role(gtk::AccessibleRole::Button);
 
             klass.bind_template();
         }
@@ -108,14 +147,23 @@ mod imp {
             self.obj()
                 .set_cursor(gtk::gdk::Cursor::from_name("grab", None).as_ref());
 
+            self.obj().set_focusable(true);
+
             self.setup_port_drag_and_drop();
+            self.setup_focus_tracking();
         }
 
         fn signals() -> &'static [Signal] {
             static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
                 vec![Signal::builder("port-toggled")
-                    // Provide id of output port and input port to signal handler.
-                    .param_types([<u32>::static_type(), <u32>::static_type()])
+                    // Provide id of output port and input port to signal handler,
+                    // together with whether a created link should linger and be passive.
+                    .param_types([
+                        <u32>::static_type(),
+                        <u32>::static_type(),
+                        <bool>::static_type(),
+                        <bool>::static_type(),
+                    ])
                     .build()]
             });
 
@@ -277,10 +325,16 @@ mod imp {
                     Direction::Input => (&other_port, &port),
                     _ => unreachable!(),
                 };
+                let options = link_options_for_modifiers(drop_target.current_event_state());
 
                 port.emit_by_name::<()>(
                     "port-toggled",
-                    &[&output_port.pipewire_id(), &input_port.pipewire_id()],
+                    &[
+                        &output_port.pipewire_id(),
+                        &input_port.pipewire_id(),
+                        &options.linger,
+                        &options.passive,
+                    ],
                 );
 
                 true
@@ -288,6 +342,127 @@ mod imp {
             obj.add_controller(drop_target);
         }
 
+        fn setup_focus_tracking(&self) {
+            let focus_controller = gtk::EventControllerFocus::new();
+
+            focus_controller.connect_enter(|controller| {
+                let port = controller.widget();
+                let Some(graph) = port
+                    .ancestor(GraphView::static_type())
+                    .and_downcast::<GraphView>()
+                else {
+                    return;
+                };
+
+                // Keep the focused port in view, as it may be far down a large node.
+                let view =
+                    graphene::Rect::new(0.0, 0.0, graph.width() as f32, graph.height() as f32);
+                if port
+                    .compute_bounds(&graph)
+                    .is_some_and(|bounds| !view.contains_rect(&bounds))
+                {
+                    graph.scroll_to_widget(&port);
+                }
+
+                // A link from a port picked up using the keyboard is drawn to the focused port.
+                graph.queue_draw();
+            });
+
+            self.obj().add_controller(focus_controller);
+        }
+
+        fn set_name(&self, name: String) {
+            self.name.replace(name);
+            self.update_label();
+        }
+
+        fn set_alias(&self, alias: String) {
+            self.alias.replace(alias);
+            self.update_label();
+        }
+
+        fn set_audio_channel(&self, audio_channel: String) {
+            self.audio_channel.replace(audio_channel);
+            self.update_label();
+        }
+
+        fn set_object_path(&self, object_path: String) {
+            self.object_path.replace(object_path);
+            self.update_label();
+        }
+
+        fn set_format(&self, format: String) {
+            self.format.replace(format);
+            self.update_label();
+        }
+
+        fn set_is_physical(&self, is_physical: bool) {
+            self.is_physical.set(is_physical);
+            self.update_handle_shape();
+            self.update_label();
+        }
+
+        fn set_is_terminal(&self, is_terminal: bool) {
+            self.is_terminal.set(is_terminal);
+            self.update_handle_shape();
+            self.update_label();
+        }
+
+        /// Give handles of physical and terminal ports a distinct shape,
+        /// so that the ends of a signal chain can be told apart from ports of streams and filters.
+        fn update_handle_shape(&self) {
+            self.handle.remove_css_class("physical");
+            self.handle.remove_css_class("terminal");
+
+            if self.is_physical.get() {
+                self.handle.add_css_class("physical");
+            } else if self.is_terminal.get() {
+                self.handle.add_css_class("terminal");
+            }
+        }
+
+        fn set_label_mode(&self, label_mode: PortLabelMode) {
+            self.label_mode.set(label_mode);
+            self.update_label();
+        }
+
+        /// Show the name selected by the label mode, falling back to the port name if the port does not have it.
+        fn update_label(&self) {
+            let display_name = self.obj().display_name();
+
+            self.label.set_text(&display_name);
+            self.label
+                .set_tooltip_text(Some(&self.tooltip_text(&display_name)));
+            self.obj()
+                .update_property(&[gtk::accessible::Property::Label(&display_name)]);
+        }
+
+        /// Get the tooltip of the port, listing all of its names in addition to the shown one.
+        fn tooltip_text(&self, display_name: &str) -> String {
+            let mut lines = vec![display_name.to_string()];
+
+            for (label, value) in [
+                ("Name", &self.name),
+                ("Alias", &self.alias),
+                ("Channel", &self.audio_channel),
+                ("Path", &self.object_path),
+                ("Format", &self.format),
+            ] {
+                let value = value.borrow();
+                if !value.is_empty() && value.as_str() != display_name {
+                    lines.push(format!("{label}: {value}"));
+                }
+            }
+
+            if self.is_physical.get() {
+                lines.push("Physical port".to_string());
+            } else if self.is_terminal.get() {
+                lines.push("Terminal port".to_string());
+            }
+
+            lines.join("\n")
+        }
+
         fn set_media_type(&self, media_type: u32) {
             let media_type = MediaType::from_raw(media_type);
 
@@ -340,6 +515,32 @@ impl Port {
             .build()
     }
 
+    /// Get the index of the port on its node, if known.
+    pub fn port_index(&self) -> Option<u32> {
+        self.imp().port_index.get()
+    }
+
+    pub fn set_port_index(&self, port_index: Option<u32>) {
+        self.imp().port_index.set(port_index);
+    }
+
+    /// Get the name of the port that is shown as its label, depending on the label mode.
+    pub fn display_name(&self) -> String {
+        let imp = self.imp();
+
+        let name = match imp.label_mode.get() {
+            PortLabelMode::Name => imp.name.borrow(),
+            PortLabelMode::Alias => imp.alias.borrow(),
+            PortLabelMode::Channel => imp.audio_channel.borrow(),
+        };
+
+        if name.is_empty() {
+            imp.name.borrow().clone()
+        } else {
+            name.clone()
+        }
+    }
+
     pub fn link_anchor(&self) -> graphene::Point {
         let style_context = self.style_context();
         let padding_right: f32 = style_context.padding().right().into();
@@ -0,0 +1,799 @@
+// Copyright 2021 Tom A. Wagner <tom.a.wagner@protonmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License version 3 as published by
+// the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Routing of the lines that links are drawn as.
+
+use adw::{
+    glib,
+    gtk::{
+        cairo,
+        graphene::{self, Point},
+    },
+};
+
+/// How the line of a link is routed from its output port to its input port.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "HelvumLinkStyle")]
+pub enum LinkStyle {
+    /// A smooth curve.
+    #[default]
+    Bezier,
+    /// Horizontal and vertical lines that are routed around nodes.
+    Orthogonal,
+    /// A straight line.
+    Straight,
+}
+
+/// Space between links that are routed around nodes and those nodes, at the default zoom level.
+const ROUTING_MARGIN: f64 = 20.0;
+
+/// How a link is routed, depending on its style and on where its anchors are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Route {
+    /// From the output straight to the input, or in a curve between them, ignoring obstacles.
+    Direct,
+    /// Horizontally from the output, vertically in a free column, and horizontally to the input.
+    Column,
+    /// Around the nodes, for links whose input is left of their output.
+    Feedback,
+}
+
+impl Route {
+    fn of(style: LinkStyle, output_x: f64, input_x: f64, margin: f64) -> Self {
+        match style {
+            LinkStyle::Straight => Self::Direct,
+            LinkStyle::Bezier if output_x <= input_x => Self::Direct,
+            LinkStyle::Orthogonal if output_x + 2.0 * margin <= input_x => Self::Column,
+            _ => Self::Feedback,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Segment {
+    Line((f64, f64)),
+    Curve((f64, f64), (f64, f64), (f64, f64)),
+}
+
+/// The line that a link is drawn as.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct LinkPath {
+    start: (f64, f64),
+    segments: Vec<Segment>,
+}
+
+impl LinkPath {
+    fn new(start: (f64, f64)) -> Self {
+        Self {
+            start,
+            segments: Vec::new(),
+        }
+    }
+
+    fn line_to(mut self, end: (f64, f64)) -> Self {
+        self.segments.push(Segment::Line(end));
+        self
+    }
+
+    fn curve_to(mut self, control1: (f64, f64), control2: (f64, f64), end: (f64, f64)) -> Self {
+        self.segments.push(Segment::Curve(control1, control2, end));
+        self
+    }
+
+    /// Add the path to the current path of the cairo context.
+    pub fn append_to(&self, cr: &cairo::Context) {
+        cr.move_to(self.start.0, self.start.1);
+        for segment in &self.segments {
+            match *segment {
+                Segment::Line((x, y)) => cr.line_to(x, y),
+                Segment::Curve((x1, y1), (x2, y2), (x3, y3)) => cr.curve_to(x1, y1, x2, y2, x3, y3),
+            }
+        }
+    }
+
+    /// Get the area that the path can cover, without the width of its line.
+    ///
+    /// Curves never leave the area spanned by their control points, so those are used instead of the actual curve.
+    pub fn bounds(&self) -> graphene::Rect {
+        let points =
+            std::iter::once(self.start).chain(self.segments.iter().flat_map(
+                |segment| match *segment {
+                    Segment::Line(end) => vec![end],
+                    Segment::Curve(control1, control2, end) => vec![control1, control2, end],
+                },
+            ));
+
+        let (min_x, max_x, min_y, max_y) = points.fold(
+            (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+            |(min_x, max_x, min_y, max_y), (x, y)| {
+                (min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y))
+            },
+        );
+
+        graphene::Rect::new(
+            min_x as f32,
+            min_y as f32,
+            (max_x - min_x) as f32,
+            (max_y - min_y) as f32,
+        )
+    }
+
+    /// Get the path scaled by `scale` and then moved by `offset`, e.g. to turn a path in canvas-space into screen-space.
+    pub fn transformed(&self, scale: f64, offset: (f64, f64)) -> Self {
+        let transform = |(x, y): (f64, f64)| (x * scale + offset.0, y * scale + offset.1);
+
+        Self {
+            start: transform(self.start),
+            segments: self
+                .segments
+                .iter()
+                .map(|segment| match *segment {
+                    Segment::Line(end) => Segment::Line(transform(end)),
+                    Segment::Curve(control1, control2, end) => {
+                        Segment::Curve(transform(control1), transform(control2), transform(end))
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Get a point roughly in the middle of the path, e.g. to draw a badge at.
+    pub fn middle(&self) -> (f64, f64) {
+        let end_of = |segment: &Segment| match *segment {
+            Segment::Line(end) | Segment::Curve(_, _, end) => end,
+        };
+
+        let count = self.segments.len();
+        if count == 0 {
+            return self.start;
+        }
+        if count % 2 == 0 {
+            // The point between the two middle segments.
+            return end_of(&self.segments[count / 2 - 1]);
+        }
+
+        // Halfway along the middle segment.
+        let start = if count == 1 {
+            self.start
+        } else {
+            end_of(&self.segments[count / 2 - 1])
+        };
+        let end = end_of(&self.segments[count / 2]);
+        ((start.0 + end.0) / 2.0, (start.1 + end.1) / 2.0)
+    }
+}
+
+/// The areas that links are routed around.
+///
+/// The areas are sorted by their left edge, so that the ones in the way of a link can be found
+/// without looking at all of them for every link.
+#[derive(Default)]
+pub(super) struct Obstacles {
+    rects: Vec<graphene::Rect>,
+    max_width: f32,
+}
+
+impl Obstacles {
+    pub fn new(rects: impl IntoIterator<Item = graphene::Rect>) -> Self {
+        let mut rects: Vec<graphene::Rect> = rects.into_iter().collect();
+        rects.sort_by(|a, b| a.x().total_cmp(&b.x()));
+        let max_width = rects.iter().map(|rect| rect.width()).fold(0.0, f32::max);
+
+        Self { rects, max_width }
+    }
+
+    /// Get the obstacles that may overlap the horizontal range from `min_x` to `max_x`.
+    ///
+    /// All obstacles that do overlap the range are returned, but some of the returned ones may not.
+    fn in_columns(&self, min_x: f64, max_x: f64) -> &[graphene::Rect] {
+        let start = self
+            .rects
+            .partition_point(|rect| f64::from(rect.x() + self.max_width) <= min_x);
+        let end = self
+            .rects
+            .partition_point(|rect| f64::from(rect.x()) < max_x);
+
+        &self.rects[start..end.max(start)]
+    }
+}
+
+/// Whether the horizontal or vertical line from `from` to `to` passes through the inside of the rectangle.
+///
+/// Lines along the edges of the rectangle do not pass through it.
+fn crosses(rect: &graphene::Rect, from: (f64, f64), to: (f64, f64)) -> bool {
+    let (left, right) = (f64::from(rect.x()), f64::from(rect.x() + rect.width()));
+    let (top, bottom) = (f64::from(rect.y()), f64::from(rect.y() + rect.height()));
+
+    left < from.0.max(to.0)
+        && from.0.min(to.0) < right
+        && top < from.1.max(to.1)
+        && from.1.min(to.1) < bottom
+}
+
+/// Route a link from an output anchor to an input anchor, both in the same space as the node areas.
+///
+/// `output_node` and `input_node` are the areas of the nodes the link is attached to, if known.
+/// Links that go backwards, from right to left, are routed around them.
+/// `obstacles` are the areas of all nodes, which orthogonal and backwards links avoid.
+pub(super) fn route_link(
+    style: LinkStyle,
+    output_anchor: &Point,
+    input_anchor: &Point,
+    output_node: Option<&graphene::Rect>,
+    input_node: Option<&graphene::Rect>,
+    obstacles: &Obstacles,
+    zoom_factor: f64,
+) -> LinkPath {
+    let (output_x, output_y) = (f64::from(output_anchor.x()), f64::from(output_anchor.y()));
+    let (input_x, input_y) = (f64::from(input_anchor.x()), f64::from(input_anchor.y()));
+    let margin = ROUTING_MARGIN * zoom_factor;
+
+    let path = LinkPath::new((output_x, output_y));
+
+    match Route::of(style, output_x, input_x, margin) {
+        Route::Direct if style == LinkStyle::Straight => return path.line_to((input_x, input_y)),
+        Route::Direct => {
+            // Place curve control offset by half the x distance between the two points.
+            // This makes the curve scale well for varying distances between the two ports.
+            let half_x_dist = (input_x - output_x) / 2.0;
+            return path.curve_to(
+                (output_x + half_x_dist, output_y),
+                (input_x - half_x_dist, input_y),
+                (input_x, input_y),
+            );
+        }
+        Route::Column => {
+            let x = free_column(
+                output_x + margin,
+                input_x - margin,
+                output_y,
+                input_y,
+                obstacles,
+                margin,
+            );
+            return path
+                .line_to((x, output_y))
+                .line_to((x, input_y))
+                .line_to((input_x, input_y));
+        }
+        Route::Feedback => {}
+    }
+
+    // The input is left of the output, e.g. for a feedback loop.
+    // Leave the output to the right, pass the nodes below or above them, and enter the input from the left.
+    let right = output_x + margin;
+    let left = input_x - margin;
+    let y = feedback_row(
+        (output_x, output_y),
+        (input_x, input_y),
+        output_node,
+        input_node,
+        obstacles,
+        margin,
+    );
+
+    if style == LinkStyle::Orthogonal {
+        path.line_to((right, output_y))
+            .line_to((right, y))
+            .line_to((left, y))
+            .line_to((left, input_y))
+            .line_to((input_x, input_y))
+    } else {
+        path.curve_to(
+            (right + margin, output_y),
+            (right + margin, y),
+            (output_x, y),
+        )
+        .line_to((input_x, y))
+        .curve_to(
+            (left - margin, y),
+            (left - margin, input_y),
+            (input_x, input_y),
+        )
+    }
+}
+
+/// Get the horizontal range in which obstacles can affect the route of a link, see [`route_link`].
+///
+/// Returns `None` if the route of the link does not depend on obstacles at all.
+pub(super) fn obstacle_columns(
+    style: LinkStyle,
+    output_anchor: &Point,
+    input_anchor: &Point,
+    zoom_factor: f64,
+) -> Option<(f64, f64)> {
+    let (output_x, input_x) = (f64::from(output_anchor.x()), f64::from(input_anchor.x()));
+    let margin = ROUTING_MARGIN * zoom_factor;
+
+    match Route::of(style, output_x, input_x, margin) {
+        Route::Direct => None,
+        // See `free_column`, which also looks at obstacles right next to the columns it can use.
+        Route::Column => Some((output_x, input_x)),
+        // See `feedback_row`.
+        Route::Feedback => Some((input_x - 2.0 * margin, output_x + 2.0 * margin)),
+    }
+}
+
+/// Get an area that the route of a link stays within, without routing it.
+///
+/// Links that go backwards may pass their nodes anywhere below or above them,
+/// so their area is only limited horizontally.
+pub(super) fn route_area(
+    style: LinkStyle,
+    output_anchor: &Point,
+    input_anchor: &Point,
+    zoom_factor: f64,
+) -> graphene::Rect {
+    let (output_x, output_y) = (output_anchor.x(), output_anchor.y());
+    let (input_x, input_y) = (input_anchor.x(), input_anchor.y());
+    let margin = ROUTING_MARGIN * zoom_factor;
+
+    if Route::of(style, f64::from(output_x), f64::from(input_x), margin) == Route::Feedback {
+        let margin = 2.0 * margin as f32;
+        let (left, right) = (
+            input_x.min(output_x) - margin,
+            input_x.max(output_x) + margin,
+        );
+        return graphene::Rect::new(left, f32::MIN / 4.0, right - left, f32::MAX / 2.0);
+    }
+
+    let (left, top) = (output_x.min(input_x), output_y.min(input_y));
+    graphene::Rect::new(
+        left,
+        top,
+        output_x.max(input_x) - left,
+        output_y.max(input_y) - top,
+    )
+}
+
+/// Find an x coordinate between `min_x` and `max_x` for the vertical line of a link that leaves
+/// the output at `output_y` and enters the input at `input_y`, preferring the middle.
+///
+/// The line is placed so that neither it nor the horizontal lines leading to and from it
+/// cross any of the obstacles. If that is not possible, e.g. because a node lies across the height
+/// of one of the ports, only the vertical line avoids the obstacles.
+/// If not even that is possible, the middle is returned.
+///
+/// Obstacles within `margin` of the ports are not avoided by the horizontal lines,
+/// as those are usually the nodes the link is attached to.
+fn free_column(
+    min_x: f64,
+    max_x: f64,
+    output_y: f64,
+    input_y: f64,
+    obstacles: &Obstacles,
+    margin: f64,
+) -> f64 {
+    let middle = (min_x + max_x) / 2.0;
+    let (top, bottom) = (output_y.min(input_y), output_y.max(input_y));
+    // Only obstacles in the columns the link passes through can block it or be next to a free column.
+    let obstacles = obstacles.in_columns(min_x - margin, max_x + margin);
+
+    let column_is_free = |x: f64| {
+        !obstacles
+            .iter()
+            .any(|rect| crosses(rect, (x, top), (x, bottom)))
+    };
+    let rows_are_free = |x: f64| {
+        !obstacles.iter().any(|rect| {
+            crosses(rect, (min_x, output_y), (x, output_y))
+                || crosses(rect, (x, input_y), (max_x, input_y))
+        })
+    };
+
+    // Besides the middle, lines right next to obstacles are the only candidates that need to be tried.
+    let candidates = || {
+        std::iter::once(middle)
+            .chain(obstacles.iter().flat_map(|rect| {
+                [
+                    f64::from(rect.x()) - margin,
+                    f64::from(rect.x() + rect.width()) + margin,
+                ]
+            }))
+            .filter(|&x| min_x <= x && x <= max_x)
+    };
+    let closest_to_middle = |is_free: &dyn Fn(f64) -> bool| {
+        candidates()
+            .filter(|&x| is_free(x))
+            .min_by(|a, b| (a - middle).abs().total_cmp(&(b - middle).abs()))
+    };
+
+    closest_to_middle(&|x| column_is_free(x) && rows_are_free(x))
+        .or_else(|| closest_to_middle(&column_is_free))
+        .unwrap_or(middle)
+}
+
+/// Find the y coordinate that a backwards link passes the nodes at, below or above them,
+/// so that the horizontal part of the link does not cross any of the obstacles.
+fn feedback_row(
+    output: (f64, f64),
+    input: (f64, f64),
+    output_node: Option<&graphene::Rect>,
+    input_node: Option<&graphene::Rect>,
+    obstacles: &Obstacles,
+    margin: f64,
+) -> f64 {
+    let nodes = || output_node.into_iter().chain(input_node);
+    let bottom = nodes()
+        .map(|rect| f64::from(rect.y() + rect.height()))
+        .fold(output.1.max(input.1), f64::max)
+        + margin;
+    let top = nodes()
+        .map(|rect| f64::from(rect.y()))
+        .fold(output.1.min(input.1), f64::min)
+        - margin;
+
+    // Take the shorter way around the nodes.
+    let below = (bottom - output.1) + (bottom - input.1) <= (output.1 - top) + (input.1 - top);
+    let mut y = if below { bottom } else { top };
+
+    // Move the line further away until it passes all obstacles between the two ends.
+    let (min_x, max_x) = (input.0 - 2.0 * margin, output.0 + 2.0 * margin);
+    let obstacles = obstacles.in_columns(min_x, max_x);
+    for _ in 0..=obstacles.len() {
+        let blocking = obstacles
+            .iter()
+            .find(|rect| crosses(rect, (min_x, y), (max_x, y)));
+
+        match blocking {
+            Some(rect) if below => y = f64::from(rect.y() + rect.height()) + margin,
+            Some(rect) => y = f64::from(rect.y()) - margin,
+            None => break,
+        }
+    }
+
+    y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> graphene::Rect {
+        graphene::Rect::new(x, y, width, height)
+    }
+
+    /// Get the start of the path and the ends of all of its segments.
+    fn corners(path: &LinkPath) -> Vec<(f64, f64)> {
+        std::iter::once(path.start)
+            .chain(path.segments.iter().map(|segment| match *segment {
+                Segment::Line(end) | Segment::Curve(_, _, end) => end,
+            }))
+            .collect()
+    }
+
+    fn route(
+        style: LinkStyle,
+        output: (f32, f32),
+        input: (f32, f32),
+        nodes: Option<(graphene::Rect, graphene::Rect)>,
+        obstacles: &[graphene::Rect],
+    ) -> LinkPath {
+        route_link(
+            style,
+            &Point::new(output.0, output.1),
+            &Point::new(input.0, input.1),
+            nodes.as_ref().map(|(output_node, _)| output_node),
+            nodes.as_ref().map(|(_, input_node)| input_node),
+            &Obstacles::new(obstacles.iter().copied()),
+            1.0,
+        )
+    }
+
+    #[test]
+    fn straight_link_is_a_single_line() {
+        let path = route(LinkStyle::Straight, (200.0, 0.0), (0.0, 50.0), None, &[]);
+
+        assert_eq!(corners(&path), vec![(200.0, 0.0), (0.0, 50.0)]);
+        assert!(matches!(path.segments[..], [Segment::Line(_)]));
+    }
+
+    #[test]
+    fn forward_bezier_link_is_a_single_curve() {
+        let path = route(LinkStyle::Bezier, (0.0, 0.0), (100.0, 50.0), None, &[]);
+
+        let [Segment::Curve(control1, control2, end)] = path.segments[..] else {
+            panic!("Expected a single curve, got {:?}", path.segments);
+        };
+        assert_eq!(
+            (control1, control2, end),
+            ((50.0, 0.0), (50.0, 50.0), (100.0, 50.0))
+        );
+    }
+
+    #[test]
+    fn forward_orthogonal_link_uses_middle_column_if_free() {
+        let path = route(LinkStyle::Orthogonal, (0.0, 0.0), (200.0, 100.0), None, &[]);
+
+        assert_eq!(
+            corners(&path),
+            vec![(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (200.0, 100.0)]
+        );
+    }
+
+    #[test]
+    fn backward_orthogonal_link_is_routed_around_nodes() {
+        let nodes = (rect(100.0, 0.0, 100.0, 100.0), rect(0.0, 0.0, 100.0, 100.0));
+        let path = route(
+            LinkStyle::Orthogonal,
+            (200.0, 60.0),
+            (0.0, 60.0),
+            Some(nodes),
+            &[nodes.0, nodes.1],
+        );
+
+        assert_eq!(
+            corners(&path),
+            vec![
+                (200.0, 60.0),
+                (220.0, 60.0),
+                (220.0, 120.0),
+                (-20.0, 120.0),
+                (-20.0, 60.0),
+                (0.0, 60.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn free_column_prefers_middle() {
+        let obstacles = Obstacles::new([rect(0.0, 0.0, 10.0, 100.0)]);
+
+        assert_eq!(
+            free_column(20.0, 180.0, 0.0, 100.0, &obstacles, 20.0),
+            100.0
+        );
+    }
+
+    #[test]
+    fn free_column_passes_closest_to_middle_next_to_obstacle() {
+        let obstacles = Obstacles::new([rect(90.0, 30.0, 30.0, 40.0)]);
+
+        assert_eq!(free_column(20.0, 180.0, 0.0, 100.0, &obstacles, 20.0), 70.0);
+    }
+
+    #[test]
+    fn free_column_avoids_obstacles_on_rows_of_ports() {
+        // The column right of the obstacle is closer to the middle,
+        // but the line leading to it from the output would cross the obstacle.
+        let obstacles = Obstacles::new([rect(60.0, -10.0, 50.0, 20.0)]);
+
+        assert_eq!(free_column(20.0, 180.0, 0.0, 100.0, &obstacles, 20.0), 40.0);
+    }
+
+    #[test]
+    fn free_column_only_avoids_obstacles_with_column_if_rows_are_blocked() {
+        // The obstacle lies across the row of the output right next to the output node.
+        let obstacles = Obstacles::new([rect(30.0, -10.0, 20.0, 20.0)]);
+
+        assert_eq!(
+            free_column(20.0, 180.0, 0.0, 100.0, &obstacles, 20.0),
+            100.0
+        );
+    }
+
+    #[test]
+    fn free_column_falls_back_to_middle() {
+        let obstacles = Obstacles::new([rect(0.0, -50.0, 300.0, 200.0)]);
+
+        assert_eq!(
+            free_column(20.0, 180.0, 0.0, 100.0, &obstacles, 20.0),
+            100.0
+        );
+    }
+
+    #[test]
+    fn feedback_row_takes_shorter_way_around_nodes() {
+        let (output_node, input_node) =
+            (rect(100.0, 0.0, 100.0, 100.0), rect(0.0, 0.0, 100.0, 100.0));
+        let obstacles = Obstacles::default();
+        let row = |y| {
+            feedback_row(
+                (200.0, y),
+                (0.0, y),
+                Some(&output_node),
+                Some(&input_node),
+                &obstacles,
+                20.0,
+            )
+        };
+
+        assert_eq!(row(60.0), 120.0);
+        assert_eq!(row(10.0), -20.0);
+    }
+
+    #[test]
+    fn feedback_row_moves_past_obstacles() {
+        let (output_node, input_node) =
+            (rect(100.0, 0.0, 100.0, 100.0), rect(0.0, 0.0, 100.0, 100.0));
+        let obstacles = Obstacles::new([
+            rect(50.0, 110.0, 50.0, 40.0),
+            rect(120.0, 160.0, 20.0, 20.0),
+            // Obstacles that are not between the ends of the link are ignored.
+            rect(400.0, 190.0, 20.0, 40.0),
+        ]);
+
+        assert_eq!(
+            feedback_row(
+                (200.0, 60.0),
+                (0.0, 60.0),
+                Some(&output_node),
+                Some(&input_node),
+                &obstacles,
+                20.0,
+            ),
+            200.0
+        );
+    }
+
+    #[test]
+    fn obstacles_in_columns_include_all_overlapping_obstacles() {
+        let obstacles = Obstacles::new([
+            rect(100.0, 0.0, 10.0, 10.0),
+            rect(0.0, 0.0, 10.0, 10.0),
+            rect(50.0, 0.0, 30.0, 10.0),
+        ]);
+        let lefts = |min_x, max_x| -> Vec<f32> {
+            obstacles
+                .in_columns(min_x, max_x)
+                .iter()
+                .map(|rect| rect.x())
+                .collect()
+        };
+
+        assert_eq!(lefts(55.0, 60.0), vec![50.0]);
+        assert!(lefts(85.0, 95.0).is_empty());
+        assert_eq!(lefts(20.0, 105.0), vec![0.0, 50.0, 100.0]);
+    }
+
+    #[test]
+    fn bounds_cover_control_points() {
+        let path = LinkPath::new((0.0, 0.0)).curve_to((50.0, -20.0), (60.0, 80.0), (100.0, 10.0));
+        let bounds = path.bounds();
+
+        assert_eq!(
+            (bounds.x(), bounds.y(), bounds.width(), bounds.height()),
+            (0.0, -20.0, 100.0, 100.0)
+        );
+    }
+
+    #[test]
+    fn middle_of_path() {
+        let path = LinkPath::new((0.0, 0.0));
+        assert_eq!(path.middle(), (0.0, 0.0));
+
+        let path = path.line_to((10.0, 0.0));
+        assert_eq!(path.middle(), (5.0, 0.0));
+
+        let path = path.line_to((10.0, 10.0));
+        assert_eq!(path.middle(), (10.0, 0.0));
+
+        let path = path.line_to((20.0, 10.0));
+        assert_eq!(path.middle(), (10.0, 5.0));
+    }
+
+    #[test]
+    fn transformed_path() {
+        let path = LinkPath::new((0.0, 0.0)).line_to((10.0, 0.0)).curve_to(
+            (20.0, 0.0),
+            (20.0, 10.0),
+            (30.0, 10.0),
+        );
+
+        assert_eq!(
+            corners(&path.transformed(2.0, (-5.0, 5.0))),
+            vec![(-5.0, 5.0), (15.0, 5.0), (55.0, 25.0)]
+        );
+        assert_eq!(
+            path.transformed(2.0, (-5.0, 5.0)).segments[1],
+            Segment::Curve((35.0, 5.0), (35.0, 25.0), (55.0, 25.0))
+        );
+    }
+
+    #[test]
+    fn routes_scale_with_zoom() {
+        let nodes = [
+            rect(200.0, 0.0, 100.0, 100.0),
+            rect(0.0, 0.0, 100.0, 100.0),
+            rect(120.0, 40.0, 60.0, 100.0),
+        ];
+        let zoomed = nodes.map(|rect| rect.scale(2.0, 2.0));
+
+        for style in [
+            LinkStyle::Straight,
+            LinkStyle::Bezier,
+            LinkStyle::Orthogonal,
+        ] {
+            for (output, input) in [((100.0, 50.0), (200.0, 60.0)), ((300.0, 50.0), (0.0, 60.0))] {
+                let path = route_link(
+                    style,
+                    &Point::new(output.0, output.1),
+                    &Point::new(input.0, input.1),
+                    Some(&nodes[0]),
+                    Some(&nodes[1]),
+                    &Obstacles::new(nodes),
+                    1.0,
+                );
+                let zoomed_path = route_link(
+                    style,
+                    &Point::new(output.0 * 2.0, output.1 * 2.0),
+                    &Point::new(input.0 * 2.0, input.1 * 2.0),
+                    Some(&zoomed[0]),
+                    Some(&zoomed[1]),
+                    &Obstacles::new(zoomed),
+                    2.0,
+                );
+
+                assert_eq!(path.transformed(2.0, (0.0, 0.0)), zoomed_path);
+            }
+        }
+    }
+
+    #[test]
+    fn route_area_contains_route() {
+        let nodes = (rect(100.0, 0.0, 100.0, 100.0), rect(0.0, 0.0, 100.0, 100.0));
+        let obstacles = [nodes.0, nodes.1, rect(40.0, 90.0, 120.0, 300.0)];
+
+        for style in [
+            LinkStyle::Straight,
+            LinkStyle::Bezier,
+            LinkStyle::Orthogonal,
+        ] {
+            for (output, input) in [
+                ((0.0, 0.0), (200.0, 100.0)),
+                ((0.0, 100.0), (20.0, 0.0)),
+                ((200.0, 50.0), (0.0, 60.0)),
+            ] {
+                let path = route(style, output, input, Some(nodes), &obstacles);
+                let area = route_area(
+                    style,
+                    &Point::new(output.0, output.1),
+                    &Point::new(input.0, input.1),
+                    1.0,
+                );
+
+                assert!(
+                    area.contains_rect(&path.bounds()),
+                    "{style:?} {output:?} {input:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn obstacle_columns_of_routes() {
+        let columns = |style, output_x: f32, input_x: f32| {
+            obstacle_columns(
+                style,
+                &Point::new(output_x, 0.0),
+                &Point::new(input_x, 100.0),
+                1.0,
+            )
+        };
+
+        assert_eq!(columns(LinkStyle::Straight, 200.0, 0.0), None);
+        assert_eq!(columns(LinkStyle::Bezier, 0.0, 200.0), None);
+        assert_eq!(columns(LinkStyle::Bezier, 200.0, 0.0), Some((-40.0, 240.0)));
+        assert_eq!(
+            columns(LinkStyle::Orthogonal, 0.0, 200.0),
+            Some((0.0, 200.0))
+        );
+        assert_eq!(
+            columns(LinkStyle::Orthogonal, 0.0, 20.0),
+            Some((-20.0, 40.0))
+        );
+    }
+}
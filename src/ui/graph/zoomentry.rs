@@ -33,6 +33,10 @@ mod imp {
             menu.append(Some("150%"), Some("win.set-zoom(1.5)"));
             menu.append(Some("200%"), Some("win.set-zoom(2.0)"));
             menu.append(Some("300%"), Some("win.set-zoom(3.0)"));
+            let fit_section = gio::Menu::new();
+            fit_section.append(Some("Zoom to Fit"), Some("win.zoom-to-fit"));
+            fit_section.append(Some("Zoom to Selection"), Some("win.zoom-to-selection"));
+            menu.append_section(None, &fit_section);
             let popover = gtk::PopoverMenu::from_model(Some(&menu));
             popover.set_position(gtk::PositionType::Top);
 
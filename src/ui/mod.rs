@@ -22,3 +22,5 @@ pub mod graph;
 
 mod window;
 pub use window::*;
+mod remote_view;
+pub use remote_view::*;
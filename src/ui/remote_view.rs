@@ -0,0 +1,137 @@
+// Copyright 2021 Tom A. Wagner <tom.a.wagner@protonmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License version 3 as published by
+// the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use adw::{glib, gtk, prelude::*, subclass::prelude::*};
+
+use super::graph;
+
+mod imp {
+    use super::*;
+
+    #[derive(Default, gtk::CompositeTemplate, glib::Properties)]
+    #[properties(wrapper_type = super::RemoteView)]
+    #[template(file = "remote_view.ui")]
+    pub struct RemoteView {
+        #[template_child]
+        #[property(type = adw::Banner, get = |_| self.connection_banner.clone())]
+        pub connection_banner: TemplateChild<adw::Banner>,
+        #[template_child]
+        #[property(type = adw::ToastOverlay, get = |_| self.toast_overlay.clone())]
+        pub toast_overlay: TemplateChild<adw::ToastOverlay>,
+        #[template_child]
+        #[property(type = graph::GraphView, get = |_| self.graph.clone())]
+        pub graph: TemplateChild<graph::GraphView>,
+        #[template_child]
+        pub trace_revealer: TemplateChild<gtk::Revealer>,
+        #[template_child]
+        pub trace_close_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub trace_list: TemplateChild<gtk::ListBox>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for RemoteView {
+        const NAME: &'static str = "HelvumRemoteView";
+        type Type = super::RemoteView;
+        type ParentType = gtk::Box;
+
+        fn class_init(klass: &mut Self::Class) {
+            // Ensure custom types are registered
+            graph::GraphView::ensure_type();
+            graph::ZoomEntry::ensure_type();
+            graph::Minimap::ensure_type();
+
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for RemoteView {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.graph.connect_local(
+                "signal-trace-changed",
+                false,
+                glib::clone!(@weak self as imp => @default-return None, move |_| {
+                    imp.update_trace_list();
+                    None
+                }),
+            );
+
+            self.trace_close_button
+                .connect_clicked(glib::clone!(@weak self as imp => move |_| {
+                    imp.graph.clear_signal_trace();
+                }));
+        }
+    }
+
+    impl WidgetImpl for RemoteView {}
+    impl BoxImpl for RemoteView {}
+
+    impl RemoteView {
+        /// List the nodes at which the traced signal path ends, showing the list only while a path is traced.
+        fn update_trace_list(&self) {
+            while let Some(ref row) = self.trace_list.first_child() {
+                self.trace_list.remove(row);
+            }
+
+            let Some(endpoints) = self.graph.signal_trace_endpoints() else {
+                self.trace_revealer.set_reveal_child(false);
+                return;
+            };
+
+            for node in endpoints {
+                let row = adw::ActionRow::builder()
+                    .title(glib::markup_escape_text(&node.display_name()))
+                    .activatable(true)
+                    .build();
+
+                // Focusing the node scrolls the graph to it.
+                row.connect_activated(glib::clone!(@weak node => move |_| {
+                    node.grab_focus();
+                }));
+
+                self.trace_list.append(&row);
+            }
+
+            self.trace_revealer.set_reveal_child(true);
+        }
+    }
+}
+
+glib::wrapper! {
+    /// The view of the graph of a single pipewire remote, shown in a tab of the window.
+    pub struct RemoteView(ObjectSubclass<imp::RemoteView>)
+        @extends gtk::Box, gtk::Widget,
+        @implements gtk::Orientable;
+}
+
+impl RemoteView {
+    pub fn new() -> Self {
+        glib::Object::new()
+    }
+}
+
+impl Default for RemoteView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,10 +1,18 @@
-use adw::{gio, gtk, prelude::*, subclass::prelude::*};
+use adw::{
+    gio,
+    glib::{self, clone},
+    gtk,
+    prelude::*,
+    subclass::prelude::*,
+};
 
-use super::graph;
+use super::{graph, RemoteView};
 
 mod imp {
     use super::*;
 
+    use std::cell::RefCell;
+
     #[derive(Default, gtk::CompositeTemplate, glib::Properties)]
     #[properties(wrapper_type = super::Window)]
     #[template(file = "window.ui")]
@@ -12,14 +20,22 @@ mod imp {
         #[template_child]
         pub header_bar: TemplateChild<adw::HeaderBar>,
         #[template_child]
-        #[property(type = adw::Banner, get = |_| self.connection_banner.clone())]
-        pub connection_banner: TemplateChild<adw::Banner>,
-        #[template_child]
         #[property(type = gtk::Label, get = |_| self.current_remote_label.clone())]
         pub current_remote_label: TemplateChild<gtk::Label>,
         #[template_child]
-        #[property(type = graph::GraphView, get = |_| self.graph.clone())]
-        pub graph: TemplateChild<graph::GraphView>,
+        #[property(type = gtk::MenuButton, get = |_| self.remote_button.clone())]
+        pub remote_button: TemplateChild<gtk::MenuButton>,
+        #[template_child]
+        #[property(type = gio::Menu, get = |_| self.remote_menu.clone())]
+        pub remote_menu: TemplateChild<gio::Menu>,
+        #[template_child]
+        #[property(type = adw::TabView, get = |_| self.tab_view.clone())]
+        pub tab_view: TemplateChild<adw::TabView>,
+        #[template_child]
+        #[property(type = gio::Menu, get = |_| self.media_class_menu.clone())]
+        pub media_class_menu: TemplateChild<gio::Menu>,
+        /// Binds the title of the selected tab to the remote label.
+        pub title_binding: RefCell<Option<glib::Binding>>,
     }
 
     #[glib::object_subclass]
@@ -29,10 +45,6 @@ mod imp {
         type ParentType = adw::ApplicationWindow;
 
         fn class_init(klass: &mut Self::Class) {
-            // Ensure custom types are registered
-            graph::GraphView::ensure_type();
-            graph::ZoomEntry::ensure_type();
-
             klass.bind_template();
         }
 
@@ -42,7 +54,35 @@ mod imp {
     }
 
     #[glib::derived_properties]
-    impl ObjectImpl for Window {}
+    impl ObjectImpl for Window {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            // Show the name of the remote of the currently selected tab in the header bar,
+            // following the title of the tab in case the remote of the tab is switched.
+            self.tab_view.connect_selected_page_notify(
+                clone!(@weak self as imp => move |tab_view| {
+                    if let Some(binding) = imp.title_binding.take() {
+                        binding.unbind();
+                    }
+
+                    match tab_view.selected_page() {
+                        Some(page) => {
+                            let binding = page
+                                .bind_property("title", &*imp.current_remote_label, "label")
+                                .sync_create()
+                                .build();
+                            imp.title_binding.replace(Some(binding));
+                        }
+                        None => imp.current_remote_label.set_label(""),
+                    }
+
+                    imp.remote_button.set_sensitive(tab_view.selected_page().is_some());
+                }),
+            );
+            self.remote_button.set_sensitive(false);
+        }
+    }
     impl WidgetImpl for Window {}
     impl WindowImpl for Window {}
     impl ApplicationWindowImpl for Window {}
@@ -59,6 +99,19 @@ impl Window {
     pub fn new() -> Self {
         glib::Object::new()
     }
+
+    /// Get the view of the remote shown in the currently selected tab, if any.
+    pub fn current_view(&self) -> Option<RemoteView> {
+        self.imp()
+            .tab_view
+            .selected_page()
+            .and_then(|page| page.child().downcast::<RemoteView>().ok())
+    }
+
+    /// Get the graph of the remote shown in the currently selected tab, if any.
+    pub fn current_graph(&self) -> Option<graph::GraphView> {
+        self.current_view().map(|view| view.graph())
+    }
 }
 
 impl Default for Window {